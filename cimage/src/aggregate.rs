@@ -71,6 +71,61 @@ impl Aggregate {
         self.experiments.push(dataset.path.clone());
     }
 
+    /// Median-normalize each experiment's log2 ratios in place, to correct
+    /// for systematic per-experiment skew (e.g. uneven labeling/loading)
+    /// before merging medians across experiments in [`Aggregate::condense`].
+    /// For each experiment index, this subtracts that experiment's global
+    /// median log2-ratio from every quantified ratio reported in it.
+    /// Ratios that are `None` or non-positive are left untouched.
+    pub fn normalize_log2_ratios(&mut self) {
+        let n = self.experiments.len();
+        let mut logs: Vec<Vec<f64>> = vec![Vec::new(); n];
+        for proteins in self.proteins.values() {
+            for protein in proteins.iter().flatten() {
+                for peptide in &protein.peptides {
+                    for (idx, ratio) in peptide.ratios.iter().enumerate() {
+                        if let Some(r) = ratio {
+                            if *r > 0.0 {
+                                logs[idx].push((*r as f64).log2());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let medians: Vec<f64> = logs
+            .into_iter()
+            .map(|mut v| {
+                if v.is_empty() {
+                    return 0.0;
+                }
+                v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let mid = v.len() / 2;
+                if v.len() % 2 == 1 {
+                    v[mid]
+                } else {
+                    (v[mid] + v[mid - 1]) / 2.0
+                }
+            })
+            .collect();
+
+        for proteins in self.proteins.values_mut() {
+            for protein in proteins.iter_mut().flatten() {
+                for peptide in &mut protein.peptides {
+                    for (idx, ratio) in peptide.ratios.iter_mut().enumerate() {
+                        if let Some(r) = ratio {
+                            if *r > 0.0 {
+                                let normalized = (*r as f64).log2() - medians[idx];
+                                *r = normalized.exp2() as f32;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     /// Condense an aggregate dataset, taking the median ratio for each peptide
     /// in each experiment.
     ///
@@ -82,62 +137,114 @@ impl Aggregate {
     /// sequence in the corresponding experiment, where the index of the item
     /// in `ratios` == index of experiment in `self.experiments`
     pub fn condense<P: AsRef<str>>(self, path: P, collapse: bool) -> Grouped {
-        let mut proteins = HashMap::new();
-        for (acc, mut experiments) in self.proteins {
-            // Hashmap for mapping peptide residue to a constructed Peptide,
-            // where the ratios contained within the peptide represent the
-            // median ratio across the experiments contained in `self`
-            let mut map: HashMap<Residue, Peptide> = HashMap::new();
-            let mut desc = "";
-            // Iterate over the Vec of Option<Protein>, where each entry
-            // in the vector represents data from one of the constituent
-            // experiments
-            //
-            // If ex == None, it means the protein was not detected in the
-            // experiment, or was later filtered out
-            let n = experiments.len();
-            for (idx, ex) in experiments.iter_mut().enumerate() {
-                // Protein was detected in experiment #idx
-                if let Some(protein) = ex.as_mut() {
-                    if collapse {
-                        // protein.collapse_redundant_sites();
-                    }
-                    desc = &protein.description;
-                    for peptide in &protein.peptides {
-                        // Insert a vector of Nones with length equal to number of experiments
-                        // if this is the first time we've encountered this
-                        // sequence.
-                        let data = map.entry(peptide.residue).or_insert_with(|| Peptide {
-                            sequence: peptide.sequence.clone(),
-                            residue: peptide.residue,
-                            ms2: 0,
-                            ratios: (0..n).map(|_| None).collect::<Vec<_>>(),
-                        });
-                        // Set the value for this experiment
-                        data.ratios[idx] = peptide.median_ratio();
-
-                        // Add in more ms2 events
-                        data.ms2 += peptide.ms2;
-                    }
-                }
-            }
-
-            // Collect all of the constructed peptides into a new `Protein`
-            // struct
-            let mut prot = map
-                .into_iter()
-                .map(|(_, peptide)| peptide)
-                .collect::<Protein>();
-
-            // FromIterator doesn't carry over acc/description, so manually move
-            prot.accession = acc.clone();
-            prot.description = desc.into();
-            proteins.insert(acc, prot);
-        }
+        let proteins = self
+            .proteins
+            .into_par_iter()
+            .map(|(acc, experiments)| Self::condense_protein(acc, experiments, collapse))
+            .collect::<HashMap<String, Protein>>();
 
         Grouped {
             proteins,
             path: path.as_ref().into(),
+            sequences: HashMap::new(),
+        }
+    }
+
+    /// Condense a single accession's per-experiment `Protein`s into one
+    /// median-ratio `Protein`, independent of every other accession so
+    /// [`Aggregate::condense`] can run this across accessions in parallel.
+    fn condense_protein(
+        acc: String,
+        mut experiments: Vec<Option<Protein>>,
+        collapse: bool,
+    ) -> (String, Protein) {
+        // Hashmap for mapping peptide residue to a constructed Peptide,
+        // where the ratios contained within the peptide represent the
+        // median ratio across the experiments contained in `self`
+        let mut map: HashMap<Residue, Peptide> = HashMap::new();
+        let mut desc = "";
+        // Iterate over the Vec of Option<Protein>, where each entry
+        // in the vector represents data from one of the constituent
+        // experiments
+        //
+        // If ex == None, it means the protein was not detected in the
+        // experiment, or was later filtered out
+        let n = experiments.len();
+        for (idx, ex) in experiments.iter_mut().enumerate() {
+            // Protein was detected in experiment #idx
+            if let Some(protein) = ex.as_mut() {
+                if collapse {
+                    // protein.collapse_redundant_sites();
+                }
+                desc = &protein.description;
+                for peptide in &protein.peptides {
+                    // Insert a vector of Nones with length equal to number of experiments
+                    // if this is the first time we've encountered this
+                    // sequence.
+                    let data = map.entry(peptide.residue).or_insert_with(|| Peptide {
+                        sequence: peptide.sequence.clone(),
+                        residue: peptide.residue,
+                        ms2: 0,
+                        ratios: (0..n).map(|_| None).collect::<Vec<_>>(),
+                        ..Peptide::default()
+                    });
+                    // Set the value for this experiment
+                    data.ratios[idx] = peptide.median_ratio();
+
+                    // Add in more ms2 events
+                    data.ms2 += peptide.ms2;
+
+                    // Carry along scan provenance from this experiment
+                    data.spectra.merge(peptide.spectra.clone());
+                }
+            }
         }
+
+        // Collect all of the constructed peptides into a new `Protein`
+        // struct
+        let mut prot = map
+            .into_iter()
+            .map(|(_, peptide)| peptide)
+            .collect::<Protein>();
+
+        // FromIterator doesn't carry over acc/description, so manually move
+        prot.accession = acc.clone();
+        prot.description = desc.into();
+        (acc, prot)
+    }
+
+    /// Estimate the number of distinct quantified `(accession, residue)`
+    /// sites across every constituent experiment in constant memory,
+    /// using a [`HyperLogLog`] sketch rather than materializing the
+    /// full distinct set. `p` trades memory for accuracy - see
+    /// [`HyperLogLog::new`].
+    pub fn estimate_site_cardinality(&self, p: u32) -> f64 {
+        let mut hll = HyperLogLog::new(p);
+        for (acc, experiments) in &self.proteins {
+            for protein in experiments.iter().flatten() {
+                for peptide in &protein.peptides {
+                    hll.insert(&(acc.clone(), peptide.residue));
+                }
+            }
+        }
+        hll.estimate()
+    }
+
+    /// Build a [`MinHash`] sketch of each protein's distinct peptide
+    /// sequence set, so that homologous or redundant accessions can be
+    /// clustered by [`MinHash::jaccard`] similarity before condensing,
+    /// without ever materializing the full peptide sets side by side.
+    pub fn protein_minhash_sketches(&self, k: usize) -> HashMap<String, MinHash> {
+        self.proteins
+            .iter()
+            .map(|(acc, experiments)| {
+                let sequences = experiments
+                    .iter()
+                    .flatten()
+                    .flat_map(|protein| protein.peptides.iter())
+                    .map(|peptide| peptide.sequence.replace('*', ""));
+                (acc.clone(), MinHash::from_iter(k, sequences))
+            })
+            .collect()
     }
 }
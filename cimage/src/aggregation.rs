@@ -1,11 +1,14 @@
 use super::*;
 
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io::{self, prelude::*};
 use std::path::Path;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 struct FilteredPeptide {
     residue: Residue,
     ms2: usize,
@@ -14,8 +17,13 @@ struct FilteredPeptide {
     ratios: Vec<Option<f32>>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct PeptideCollection {
     peptides: HashMap<String, Vec<FilteredPeptide>>,
+    /// Peptides from `"Reverse"` (decoy) accessions, kept so
+    /// [`PeptideCollection::target_decoy_fdr`] can estimate the empirical
+    /// FDR at a given liganded-ratio cutoff instead of it being discarded.
+    decoys: HashMap<String, Vec<FilteredPeptide>>,
     experiments: Vec<String>,
 }
 
@@ -57,26 +65,21 @@ impl PeptideCollection {
             panic!("Experiment not found in aggregate set! {}", expt);
         }
 
-        let mut indices = Vec::new();
-
         let chemotype = chemotype_map
             .get(expt)
             .expect(&format!("missing {} from chemotype map", expt));
-        for (cmpd, _) in chemotype_map.iter().filter(|(_, v)| v == &chemotype) {
-            let mut x = None;
-            for (idx, name) in self.experiments.iter().enumerate() {
-                if name == cmpd {
-                    x = Some(idx);
-                    break;
-                }
-            }
-            match x {
-                Some(idx) => indices.push(idx),
-                None => panic!(
-                    "This is very bad, there is some eldritch horror lurking in the codebase"
-                ),
-            }
-        }
+        let indices: Vec<usize> = chemotype_map
+            .par_iter()
+            .filter(|(_, v)| v == &chemotype)
+            .map(|(cmpd, _)| {
+                self.experiments
+                    .iter()
+                    .position(|name| name == cmpd)
+                    .unwrap_or_else(|| {
+                        panic!("This is very bad, there is some eldritch horror lurking in the codebase")
+                    })
+            })
+            .collect();
 
         // println!("SPF: {}, {:?}, {}", expt,indices,acc);
 
@@ -116,127 +119,194 @@ impl PeptideCollection {
 
 impl PeptideCollection {
     pub fn new(agg: &Aggregate) -> Self {
-        let mut map = HashMap::new();
+        let peptides = agg
+            .proteins
+            .par_iter()
+            .filter(|(acc, _)| !acc.contains("Reverse"))
+            .map(|(acc, experiments)| (acc.clone(), Self::condense_accession(experiments)))
+            .collect::<HashMap<String, Vec<FilteredPeptide>>>();
 
-        for (acc, experiments) in &agg.proteins {
-            if acc.contains("Reverse") {
-                continue;
-            }
-            let mut desc = "";
+        // Kept separately rather than discarded so `target_decoy_fdr` can
+        // calibrate the liganded-ratio cutoff against real decoy hits.
+        let decoys = agg
+            .proteins
+            .par_iter()
+            .filter(|(acc, _)| acc.contains("Reverse"))
+            .map(|(acc, experiments)| (acc.clone(), Self::condense_accession(experiments)))
+            .collect::<HashMap<String, Vec<FilteredPeptide>>>();
 
-            // Issue with duplicate sites being listed, since we're combining by sequence
-            let mut site_to_ratios: HashMap<_, Vec<Vec<Option<f32>>>> = HashMap::new();
-            let mut site_to_seq: HashMap<Residue, &str> = HashMap::new();
-            let mut site_to_ms2: HashMap<Residue, usize> = HashMap::new();
-
-            for (idx, ex) in experiments.iter().enumerate() {
-                if let Some(protein) = ex {
-                    desc = &protein.description;
-                    for peptide in &protein.peptides {
-                        let ratios = site_to_ratios.entry(peptide.residue).or_insert_with(|| {
-                            (0..experiments.len())
-                                .map(|_| Vec::new())
-                                .collect::<Vec<_>>()
-                        });
-
-                        // Since we may have multiple sequences that map to the
-                        // same residue in any given experiment (idx), we keep
-                        // a running list of the ratios detected in each exp,
-                        // we will calculate a median ratio once we've iterated
-                        // over all of the experiments and peptides for this protein
-                        ratios[idx].extend(peptide.ratios.iter());
-                        site_to_seq.insert(peptide.residue, &peptide.sequence);
-                        *site_to_ms2.entry(peptide.residue).or_insert(0) += peptide.ms2;
-                    }
+        PeptideCollection {
+            peptides,
+            decoys,
+            experiments: agg.experiments.clone(),
+        }
+    }
+
+    /// Condense one accession's per-experiment peptide matrices into the
+    /// final [`FilteredPeptide`] rows, independent of every other
+    /// accession so [`PeptideCollection::new`] can run this across
+    /// accessions in parallel.
+    fn condense_accession(experiments: &[Option<Protein>]) -> Vec<FilteredPeptide> {
+        let mut desc = "";
+
+        // Issue with duplicate sites being listed, since we're combining by sequence
+        let mut site_to_ratios: HashMap<_, Vec<Vec<Option<f32>>>> = HashMap::new();
+        let mut site_to_seq: HashMap<Residue, &str> = HashMap::new();
+        let mut site_to_ms2: HashMap<Residue, usize> = HashMap::new();
+
+        for (idx, ex) in experiments.iter().enumerate() {
+            if let Some(protein) = ex {
+                desc = &protein.description;
+                for peptide in &protein.peptides {
+                    let ratios = site_to_ratios.entry(peptide.residue).or_insert_with(|| {
+                        (0..experiments.len())
+                            .map(|_| Vec::new())
+                            .collect::<Vec<_>>()
+                    });
+
+                    // Since we may have multiple sequences that map to the
+                    // same residue in any given experiment (idx), we keep
+                    // a running list of the ratios detected in each exp,
+                    // we will calculate a median ratio once we've iterated
+                    // over all of the experiments and peptides for this protein
+                    ratios[idx].extend(peptide.ratios.iter());
+                    site_to_seq.insert(peptide.residue, &peptide.sequence);
+                    *site_to_ms2.entry(peptide.residue).or_insert(0) += peptide.ms2;
                 }
             }
+        }
 
-            let mut seq_to_sites: HashMap<String, Vec<Residue>> = HashMap::new();
+        let mut seq_to_sites: HashMap<String, Vec<Residue>> = HashMap::new();
 
-            for (site, seq) in &site_to_seq {
-                seq_to_sites
-                    .entry(seq.replace('*', ""))
-                    .or_insert_with(Vec::new)
-                    .push(*site);
-            }
+        for (site, seq) in &site_to_seq {
+            seq_to_sites
+                .entry(seq.replace('*', ""))
+                .or_insert_with(Vec::new)
+                .push(*site);
+        }
 
-            for (_, mut sites) in seq_to_sites {
-                sites.sort();
-                if sites.len() > 1 {
-                    let keep_residue = sites.pop().unwrap();
-                    for remove in sites {
-                        // All of these unwraps should be gucci
-                        let ms2 = site_to_ms2.remove(&remove).unwrap();
-                        *site_to_ms2.entry(keep_residue).or_insert(0) += ms2;
+        for (_, mut sites) in seq_to_sites {
+            sites.sort();
+            if sites.len() > 1 {
+                let keep_residue = sites.pop().unwrap();
+                for remove in sites {
+                    // All of these unwraps should be gucci
+                    let ms2 = site_to_ms2.remove(&remove).unwrap();
+                    *site_to_ms2.entry(keep_residue).or_insert(0) += ms2;
 
-                        let ratios = site_to_ratios.remove(&remove).unwrap();
+                    let ratios = site_to_ratios.remove(&remove).unwrap();
 
-                        let mutref = site_to_ratios.get_mut(&keep_residue).unwrap();
+                    let mutref = site_to_ratios.get_mut(&keep_residue).unwrap();
 
-                        for (idx, v) in ratios.into_iter().enumerate() {
-                            mutref[idx].extend(v.iter());
-                        }
+                    for (idx, v) in ratios.into_iter().enumerate() {
+                        mutref[idx].extend(v.iter());
                     }
                 }
             }
+        }
 
-            // At this stage we've rearranged the data into a matrix of peptide
-            // sequences and their ratios, which is essentially the final form
-            // that will be presented in an Excel file, etc. It is at this stage
-            // that we can apply filters across multiple chemotypes/compounds
-
-            let mut peptides = Vec::new();
-
-            for (residue, ratios) in site_to_ratios {
-                // Take our matrix of ratios (experiment by ratios per peptide),
-                // and condense it down into a vector of median ratios.
-                // This is essentially one row in the CSV, if output on peptide level
-                let condensed: Vec<Option<f32>> = ratios
-                    .into_iter()
-                    .map(|exp| {
-                        let mut pep = Peptide::default();
-                        pep.ratios = exp;
-                        pep.median_ratio()
-                    })
-                    .collect();
-
-                let mut twenties = 0;
-                let mut liganded = 0;
-                // let mut not_liganded = 0;
-
-                for r in &condensed {
-                    if let Some(x) = r {
-                        if *x == 20.0 {
-                            twenties += 1;
-                        } else if *x >= 4.0 {
-                            liganded += 1;
-                        } else {
-                            // not_liganded += 1;
-                        }
+        // At this stage we've rearranged the data into a matrix of peptide
+        // sequences and their ratios, which is essentially the final form
+        // that will be presented in an Excel file, etc. It is at this stage
+        // that we can apply filters across multiple chemotypes/compounds
+
+        let mut peptides = Vec::new();
+
+        for (residue, ratios) in site_to_ratios {
+            // Take our matrix of ratios (experiment by ratios per peptide),
+            // and condense it down into a vector of median ratios.
+            // This is essentially one row in the CSV, if output on peptide level
+            let condensed: Vec<Option<f32>> = ratios
+                .into_iter()
+                .map(|exp| {
+                    let mut pep = Peptide::default();
+                    pep.ratios = exp;
+                    pep.median_ratio()
+                })
+                .collect();
+
+            let mut twenties = 0;
+            let mut liganded = 0;
+            // let mut not_liganded = 0;
+
+            for r in &condensed {
+                if let Some(x) = r {
+                    if *x == 20.0 {
+                        twenties += 1;
+                    } else if *x >= 4.0 {
+                        liganded += 1;
+                    } else {
+                        // not_liganded += 1;
                     }
                 }
-
-                // if not_liganded + twenties + liganded >= 1 {
-                peptides.push(FilteredPeptide {
-                    residue,
-                    sequence: site_to_seq
-                        .get(&residue)
-                        .copied()
-                        .unwrap_or_default()
-                        .into(),
-                    desc: desc.into(),
-                    ms2: site_to_ms2.get(&residue).copied().unwrap_or_default(),
-                    ratios: condensed,
-                });
             }
-            map.insert(acc.clone(), peptides);
+
+            // if not_liganded + twenties + liganded >= 1 {
+            peptides.push(FilteredPeptide {
+                residue,
+                sequence: site_to_seq
+                    .get(&residue)
+                    .copied()
+                    .unwrap_or_default()
+                    .into(),
+                desc: desc.into(),
+                ms2: site_to_ms2.get(&residue).copied().unwrap_or_default(),
+                ratios: condensed,
+            });
         }
-        PeptideCollection {
-            peptides: map,
-            experiments: agg.experiments.clone(),
+        peptides
+    }
+
+    /// Hash `experiments` so a [`PeptideCollection`] cache can be keyed on
+    /// the set of experiments it was built from, rather than on the
+    /// (absent, since `Aggregate` isn't file-backed) mtime of a source file.
+    fn experiments_hash(experiments: &[String]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        experiments.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Serialize this collection to a bincode cache at `path`, prefixed
+    /// with a hash of `self.experiments` so [`PeptideCollection::load_cache`]
+    /// can detect a cache built from a different set of experiments and
+    /// rebuild via [`PeptideCollection::new`] instead of returning stale data.
+    pub fn save_cache<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut bytes = Self::experiments_hash(&self.experiments)
+            .to_le_bytes()
+            .to_vec();
+        bytes.extend(
+            bincode::serialize(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        );
+        fs::write(path, bytes)
+    }
+
+    /// Load a [`PeptideCollection`] previously written by
+    /// [`PeptideCollection::save_cache`]. Returns `Ok(None)` if no cache
+    /// exists at `path`, or if it was built from a set of experiments other
+    /// than `experiments`, so the caller can fall back to
+    /// [`PeptideCollection::new`] and re-cache.
+    pub fn load_cache<P: AsRef<Path>>(path: P, experiments: &[String]) -> io::Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if bytes.len() < 8 {
+            return Ok(None);
+        }
+        let mut hash_bytes = [0u8; 8];
+        hash_bytes.copy_from_slice(&bytes[..8]);
+        if u64::from_le_bytes(hash_bytes) != Self::experiments_hash(experiments) {
+            return Ok(None);
+        }
+        match bincode::deserialize(&bytes[8..]) {
+            Ok(collection) => Ok(Some(collection)),
+            Err(_) => Ok(None),
         }
     }
 
+    /// Accessions are written in sorted order, so repeated runs over the
+    /// same input produce byte-identical files.
     pub fn write_functions<P: AsRef<Path>>(&self, path: P, keywords: P) -> io::Result<()> {
         let mut f = fs::OpenOptions::new()
             .create(true)
@@ -247,7 +317,11 @@ impl PeptideCollection {
         let ann = uniprot::kw::load(keywords)?;
         writeln!(f, "accession\tdescription\tkeywords\tgo term\tenzyme")?;
 
-        for (acc, peptides) in &self.peptides {
+        let mut accessions: Vec<&String> = self.peptides.keys().collect();
+        accessions.sort();
+
+        for acc in accessions {
+            let peptides = &self.peptides[acc];
             let mut desc = "";
             if peptides.len() == 0 {
                 continue;
@@ -269,6 +343,65 @@ impl PeptideCollection {
         Ok(())
     }
 
+    /// Extend [`PeptideCollection::write_functions`] into an actual
+    /// functional-enrichment table: test each GO term (propagated through
+    /// `obo`) and each UniProt keyword for over-representation among
+    /// liganded accessions (any experiment at/above `liganded_cutoff`)
+    /// relative to every accession in this collection, via
+    /// [`go_enrichment`]/[`keyword_enrichment`]'s hypergeometric test with
+    /// Benjamini-Hochberg correction.
+    pub fn write_enrichment<P: AsRef<Path>>(
+        &self,
+        path: P,
+        keywords: P,
+        obo: P,
+        liganded_cutoff: f32,
+    ) -> io::Result<()> {
+        let ann = uniprot::kw::load(keywords)?;
+        let dag = uniprot::obo::GoDag::load(obo)?;
+
+        let background: HashSet<String> = self.peptides.keys().cloned().collect();
+        let foreground: HashSet<String> = self
+            .peptides
+            .iter()
+            .filter(|(_, peptides)| {
+                peptides.iter().any(|p| {
+                    p.ratios
+                        .iter()
+                        .any(|r| r.map_or(false, |x| x >= liganded_cutoff))
+                })
+            })
+            .map(|(acc, _)| acc.clone())
+            .collect();
+
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+
+        writeln!(f, "source\tterm\tfold_enrichment\tp_value\tq_value")?;
+        for r in go_enrichment(&foreground, &background, &ann, &dag) {
+            writeln!(
+                f,
+                "go\t{}\t{}\t{}\t{}",
+                r.term, r.fold_enrichment, r.p_value, r.q_value
+            )?;
+        }
+        for r in keyword_enrichment(&foreground, &background, &ann) {
+            writeln!(
+                f,
+                "keyword\t{}\t{}\t{}\t{}",
+                r.term, r.fold_enrichment, r.p_value, r.q_value
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Accessions are written in sorted order, and peptides within each
+    /// accession are sorted by residue, so repeated runs over the same
+    /// input produce byte-identical files.
     pub fn write_peptides<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
         let mut f = fs::OpenOptions::new()
             .create(true)
@@ -279,7 +412,7 @@ impl PeptideCollection {
 
         writeln!(
             f,
-            "accession\tgene_name\tdescription\tsequence\tsite\tmax_ratio\taverage_ratio\t{}",
+            "accession\tgene_name\tdescription\tsequence\tsite\tmax_ratio\taverage_ratio\tq_value\t{}",
             self.experiments
                 .iter()
                 .map(|p| p.clone())
@@ -287,7 +420,25 @@ impl PeptideCollection {
                 .join("\t")
         )?;
 
-        for (acc, peptides) in &self.peptides {
+        let target_cutoffs: Vec<f32> = self
+            .peptides
+            .values()
+            .flatten()
+            .filter_map(|p| {
+                p.ratios
+                    .iter()
+                    .filter_map(|x| *x)
+                    .fold(None, Self::max_ratio)
+            })
+            .collect();
+        let q_values = Self::q_value_by_cutoff(&self.target_decoy_fdr(&target_cutoffs));
+
+        let mut accessions: Vec<&String> = self.peptides.keys().collect();
+        accessions.sort();
+
+        for acc in accessions {
+            let mut peptides: Vec<&FilteredPeptide> = self.peptides[acc].iter().collect();
+            peptides.sort_by_key(|p| p.residue);
             for peptide in peptides {
                 let mut max = 0.;
                 let mut sum = 0.;
@@ -303,9 +454,10 @@ impl PeptideCollection {
                 if n == 0 {
                     // continue;
                 }
+                let q = q_values.get(&max.to_bits()).copied().unwrap_or(1.0);
                 writeln!(
                     f,
-                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
                     acc,
                     peptide.desc.split(' ').next().unwrap_or_default(),
                     peptide.desc,
@@ -313,6 +465,7 @@ impl PeptideCollection {
                     peptide.residue,
                     max,
                     sum / (n as f32),
+                    q,
                     peptide
                         .ratios
                         .iter()
@@ -325,6 +478,10 @@ impl PeptideCollection {
         Ok(())
     }
 
+    fn max_ratio(acc: Option<f32>, r: f32) -> Option<f32> {
+        Some(acc.map_or(r, |m| if r > m { r } else { m }))
+    }
+
     ///
     /// Args:
     /// * chemotype_map: map a compound name to a chemotype, e.g. (HA17, Scouts) or (DAPG1, DAPG)
@@ -334,6 +491,7 @@ impl PeptideCollection {
     ) -> PeptideCollection {
         let mut reorg = PeptideCollection {
             peptides: HashMap::new(),
+            decoys: HashMap::new(),
             experiments: chemotype_map
                 .values()
                 .cloned()
@@ -418,6 +576,7 @@ impl PeptideCollection {
         let mut pc = PeptideCollection {
             experiments: indices.iter().map(|(_, cmpd)| *cmpd).cloned().collect(),
             peptides: HashMap::new(),
+            decoys: HashMap::new(),
         };
 
         for (acc, peptides) in &self.peptides {
@@ -442,6 +601,8 @@ impl PeptideCollection {
         pc
     }
 
+    /// Accessions are written in sorted order, so repeated runs over the
+    /// same input produce byte-identical files.
     pub fn write_proteins<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
         let mut f = fs::OpenOptions::new()
             .create(true)
@@ -459,51 +620,68 @@ impl PeptideCollection {
                 .join("\t")
         )?;
 
-        for (acc, peptides) in &self.peptides {
-            let mut selected_ratios: Vec<f32> = (0..self.experiments.len()).map(|_| 0f32).collect();
-            let mut desc = "";
-            let mut n = 0;
-            let mut max = 0.;
-            let mut sum = 0.;
-            for peptide in peptides {
-                desc = &peptide.desc;
-                for (idx, r) in peptide.ratios.iter().enumerate() {
-                    // take max ratio of all the peptides
-                    if let Some(rat) = r {
-                        n += 1;
-                        if *rat > selected_ratios[idx] {
-                            selected_ratios[idx] = *rat;
-                        }
-                        if *rat > max {
-                            max = *rat;
-                        }
-                        sum += *rat;
+        let mut rows: Vec<(&String, String)> = self
+            .peptides
+            .par_iter()
+            .map(|(acc, peptides)| (acc, Self::render_protein_row(acc, peptides)))
+            .collect();
+        rows.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (_, line) in rows {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Render one [`PeptideCollection::write_proteins`] row for a single
+    /// accession, independent of every other accession so the per-accession
+    /// loop can run in parallel and be sorted into deterministic order
+    /// afterwards rather than relying on `HashMap` iteration order.
+    fn render_protein_row(acc: &str, peptides: &[FilteredPeptide]) -> String {
+        let mut selected_ratios: Vec<f32> =
+            (0..peptides.first().map(|p| p.ratios.len()).unwrap_or_default())
+                .map(|_| 0f32)
+                .collect();
+        let mut desc = "";
+        let mut n = 0;
+        let mut max = 0.;
+        let mut sum = 0.;
+        for peptide in peptides {
+            desc = &peptide.desc;
+            for (idx, r) in peptide.ratios.iter().enumerate() {
+                // take max ratio of all the peptides
+                if let Some(rat) = r {
+                    n += 1;
+                    if *rat > selected_ratios[idx] {
+                        selected_ratios[idx] = *rat;
+                    }
+                    if *rat > max {
+                        max = *rat;
                     }
+                    sum += *rat;
                 }
             }
+        }
 
-            // not detected, or not ligandend
-            if n == 0
-            /* || selected_ratios.iter().filter(|&&x| x >= 4.0).count() == 0 */
-            {
-                // continue;
-            }
-
-            writeln!(
-                f,
-                "{}\t{}\t{}\t{}\t{}",
-                acc,
-                desc,
-                max,
-                sum / (n as f32),
-                selected_ratios
-                    .into_iter()
-                    .map(Self::eliminate_zeros)
-                    .collect::<Vec<String>>()
-                    .join("\t")
-            )?;
+        // not detected, or not ligandend
+        if n == 0
+        /* || selected_ratios.iter().filter(|&&x| x >= 4.0).count() == 0 */
+        {
+            // continue;
         }
-        Ok(())
+
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            acc,
+            desc,
+            max,
+            sum / (n as f32),
+            selected_ratios
+                .into_iter()
+                .map(Self::eliminate_zeros)
+                .collect::<Vec<String>>()
+                .join("\t")
+        )
     }
 
     fn eliminate_zeros(s: f32) -> String {
@@ -514,3 +692,442 @@ impl PeptideCollection {
         }
     }
 }
+
+/// The empirical target-decoy FDR at one ratio cutoff, as computed by
+/// [`PeptideCollection::target_decoy_fdr`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FdrPoint {
+    pub cutoff: f32,
+    pub target_count: usize,
+    pub decoy_count: usize,
+    /// `decoy_count / target_count` at this cutoff, before monotonicity
+    /// correction
+    pub fdr: f64,
+    /// `fdr`, corrected so it never improves at a looser cutoff than it
+    /// does at any stricter one
+    pub q_value: f64,
+}
+
+impl PeptideCollection {
+    /// Sweep `cutoffs` and report, for each, the empirical FDR among sites
+    /// with a max ratio at/above that cutoff: `decoy_count / target_count`,
+    /// where decoys are the `"Reverse"`-accession peptides [`PeptideCollection::new`]
+    /// set aside instead of discarding. The raw per-cutoff FDR isn't
+    /// guaranteed to be monotone (a looser cutoff can, by chance, show a
+    /// lower empirical FDR than a stricter one), so each cutoff's `q_value`
+    /// is the minimum raw FDR over that cutoff and every looser one.
+    /// Results are sorted by ascending cutoff.
+    pub fn target_decoy_fdr(&self, cutoffs: &[f32]) -> Vec<FdrPoint> {
+        let mut points: Vec<FdrPoint> = cutoffs
+            .iter()
+            .map(|&cutoff| {
+                let target_count = Self::count_passing(&self.peptides, cutoff);
+                let decoy_count = Self::count_passing(&self.decoys, cutoff);
+                let fdr = if target_count == 0 {
+                    0.0
+                } else {
+                    decoy_count as f64 / target_count as f64
+                };
+                FdrPoint {
+                    cutoff,
+                    target_count,
+                    decoy_count,
+                    fdr,
+                    q_value: fdr,
+                }
+            })
+            .collect();
+
+        // Strictest cutoff first; the loop below walks back from the
+        // loosest cutoff to the strictest, carrying the running minimum.
+        points.sort_by(|a, b| b.cutoff.partial_cmp(&a.cutoff).unwrap());
+        for i in (0..points.len().saturating_sub(1)).rev() {
+            if points[i + 1].q_value < points[i].q_value {
+                points[i].q_value = points[i + 1].q_value;
+            }
+        }
+
+        points.sort_by(|a, b| a.cutoff.partial_cmp(&b.cutoff).unwrap());
+        points
+    }
+
+    /// Number of sites (across every accession in `map`) whose max ratio
+    /// is at/above `cutoff`
+    fn count_passing(map: &HashMap<String, Vec<FilteredPeptide>>, cutoff: f32) -> usize {
+        map.values()
+            .flatten()
+            .filter(|p| p.ratios.iter().any(|r| r.map_or(false, |x| x >= cutoff)))
+            .count()
+    }
+
+    /// Index [`FdrPoint`]s by their cutoff's bit pattern, so a per-site
+    /// q-value can be looked up by that site's own max ratio (which,
+    /// passed back in as one of `target_decoy_fdr`'s `cutoffs`, reproduces
+    /// the same `f32` bits) in [`PeptideCollection::write_peptides`].
+    fn q_value_by_cutoff(points: &[FdrPoint]) -> HashMap<u32, f64> {
+        points
+            .iter()
+            .map(|p| (p.cutoff.to_bits(), p.q_value))
+            .collect()
+    }
+}
+
+/// One site's place in [`PeptideCollection::rank_sites`]'s NSGA-II
+/// priority list: its three objectives (all maximized) plus the front
+/// and crowding distance it was assigned within that front.
+#[derive(Clone, Debug)]
+pub struct RankedSite {
+    pub accession: String,
+    pub residue: Residue,
+    pub sequence: String,
+    pub desc: String,
+    /// Maximum ratio observed across experiments
+    pub max_ratio: f32,
+    /// Number of experiments at/above the liganded cutoff (4.0)
+    pub reproducibility: usize,
+    pub ms2: usize,
+    /// 0-indexed Pareto front; lower is better. Sites with no detected
+    /// ratio are placed in a final front below every dominated site.
+    pub front: usize,
+    pub crowding_distance: f64,
+}
+
+impl PeptideCollection {
+    /// Rank every site across every accession by fast non-dominated sort
+    /// (NSGA-II) over three maximized objectives: max ratio, reproducibility
+    /// (count of experiments at/above the liganded cutoff), and `ms2`
+    /// spectral count. Sites with no detected ratio are excluded from
+    /// ranking and appended in a final, lowest front. The result is ordered
+    /// front-by-front, and within a front by descending crowding distance,
+    /// so it can be consumed directly as a priority list.
+    pub fn rank_sites(&self) -> Vec<RankedSite> {
+        let mut candidates = Vec::new();
+        let mut undetected = Vec::new();
+
+        for (acc, peptides) in &self.peptides {
+            for peptide in peptides {
+                let mut max_ratio = 0f32;
+                let mut reproducibility = 0usize;
+                let mut detected = false;
+                for r in peptide.ratios.iter().filter_map(|x| *x) {
+                    detected = true;
+                    if r > max_ratio {
+                        max_ratio = r;
+                    }
+                    if r >= 4.0 {
+                        reproducibility += 1;
+                    }
+                }
+
+                let site = RankedSite {
+                    accession: acc.clone(),
+                    residue: peptide.residue,
+                    sequence: peptide.sequence.clone(),
+                    desc: peptide.desc.clone(),
+                    max_ratio,
+                    reproducibility,
+                    ms2: peptide.ms2,
+                    front: 0,
+                    crowding_distance: 0.0,
+                };
+
+                if detected {
+                    candidates.push(site);
+                } else {
+                    undetected.push(site);
+                }
+            }
+        }
+
+        let fronts = Self::fast_non_dominated_sort(&candidates);
+
+        let mut ranked = Vec::new();
+        for (i, indices) in fronts.into_iter().enumerate() {
+            let mut front: Vec<RankedSite> = indices
+                .into_iter()
+                .map(|idx| candidates[idx].clone())
+                .collect();
+            Self::assign_crowding_distance(&mut front);
+            front.sort_by(|a, b| {
+                b.crowding_distance
+                    .partial_cmp(&a.crowding_distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            for site in &mut front {
+                site.front = i;
+            }
+            ranked.extend(front);
+        }
+
+        let last_front = ranked.last().map(|s| s.front + 1).unwrap_or(0);
+        for mut site in undetected {
+            site.front = last_front;
+            ranked.push(site);
+        }
+
+        ranked
+    }
+
+    /// `a` dominates `b` iff `a` is at least as good on every objective and
+    /// strictly better on at least one
+    fn dominates(a: &RankedSite, b: &RankedSite) -> bool {
+        let at_least_as_good =
+            a.max_ratio >= b.max_ratio && a.reproducibility >= b.reproducibility && a.ms2 >= b.ms2;
+        let strictly_better =
+            a.max_ratio > b.max_ratio || a.reproducibility > b.reproducibility || a.ms2 > b.ms2;
+        at_least_as_good && strictly_better
+    }
+
+    /// NSGA-II fast non-dominated sort: returns each front as a list of
+    /// indices into `sites`, front 0 first
+    fn fast_non_dominated_sort(sites: &[RankedSite]) -> Vec<Vec<usize>> {
+        let n = sites.len();
+        let mut domination_counts = vec![0usize; n];
+        let mut dominated_sets: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut first_front = Vec::new();
+
+        for p in 0..n {
+            for q in 0..n {
+                if p == q {
+                    continue;
+                }
+                if Self::dominates(&sites[p], &sites[q]) {
+                    dominated_sets[p].push(q);
+                } else if Self::dominates(&sites[q], &sites[p]) {
+                    domination_counts[p] += 1;
+                }
+            }
+            if domination_counts[p] == 0 {
+                first_front.push(p);
+            }
+        }
+
+        let mut fronts = Vec::new();
+        let mut current_front = first_front;
+        while !current_front.is_empty() {
+            let mut next_front = Vec::new();
+            for &p in &current_front {
+                for &q in &dominated_sets[p] {
+                    domination_counts[q] -= 1;
+                    if domination_counts[q] == 0 {
+                        next_front.push(q);
+                    }
+                }
+            }
+            fronts.push(current_front);
+            current_front = next_front;
+        }
+        fronts
+    }
+
+    /// Assign each member of `front` its NSGA-II crowding distance: for
+    /// each objective, sort the front by that objective, give the two
+    /// boundary members infinite distance, and accumulate the normalized
+    /// gap between neighbors for everyone else.
+    fn assign_crowding_distance(front: &mut [RankedSite]) {
+        let n = front.len();
+        if n == 0 {
+            return;
+        }
+
+        let mut distances = vec![0f64; n];
+        if n <= 2 {
+            distances.iter_mut().for_each(|d| *d = f64::INFINITY);
+        } else {
+            for objective in [
+                (&|s: &RankedSite| s.max_ratio as f64) as &dyn Fn(&RankedSite) -> f64,
+                &|s: &RankedSite| s.reproducibility as f64,
+                &|s: &RankedSite| s.ms2 as f64,
+            ] {
+                let mut order: Vec<usize> = (0..n).collect();
+                order.sort_by(|&a, &b| {
+                    objective(&front[a])
+                        .partial_cmp(&objective(&front[b]))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+
+                distances[order[0]] = f64::INFINITY;
+                distances[order[n - 1]] = f64::INFINITY;
+
+                let min = objective(&front[order[0]]);
+                let max = objective(&front[order[n - 1]]);
+                let range = max - min;
+                if range > 0.0 {
+                    for w in 1..n - 1 {
+                        let prev = objective(&front[order[w - 1]]);
+                        let next = objective(&front[order[w + 1]]);
+                        distances[order[w]] += (next - prev) / range;
+                    }
+                }
+            }
+        }
+
+        for (site, d) in front.iter_mut().zip(distances) {
+            site.crowding_distance = d;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn site(max_ratio: f32, reproducibility: usize, ms2: usize) -> RankedSite {
+        RankedSite {
+            accession: String::from("P1"),
+            residue: 0,
+            sequence: String::new(),
+            desc: String::new(),
+            max_ratio,
+            reproducibility,
+            ms2,
+            front: 0,
+            crowding_distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn dominates_requires_at_least_as_good_and_strictly_better() {
+        let a = site(10.0, 3, 5);
+        let b = site(8.0, 3, 5);
+        assert!(PeptideCollection::dominates(&a, &b));
+        assert!(!PeptideCollection::dominates(&b, &a));
+
+        // identical on every objective: neither dominates the other
+        let c = site(10.0, 3, 5);
+        assert!(!PeptideCollection::dominates(&a, &c));
+        assert!(!PeptideCollection::dominates(&c, &a));
+
+        // mixed: a better on max_ratio, b better on ms2 - incomparable
+        let d = site(10.0, 3, 4);
+        let e = site(9.0, 3, 5);
+        assert!(!PeptideCollection::dominates(&d, &e));
+        assert!(!PeptideCollection::dominates(&e, &d));
+    }
+
+    #[test]
+    fn fast_non_dominated_sort_separates_fronts() {
+        // s0 dominates s1 and s2; s1 and s2 are mutually incomparable and
+        // both dominate s3.
+        let sites = vec![
+            site(10.0, 5, 10), // s0: best on everything
+            site(8.0, 5, 10),  // s1: dominated only by s0
+            site(10.0, 3, 10), // s2: dominated only by s0
+            site(5.0, 2, 5),   // s3: dominated by s0, s1, and s2
+        ];
+
+        let fronts = PeptideCollection::fast_non_dominated_sort(&sites);
+        assert_eq!(fronts, vec![vec![0], vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn assign_crowding_distance_gives_boundaries_infinity() {
+        let mut front = vec![site(1.0, 1, 1), site(5.0, 1, 1), site(10.0, 1, 1)];
+        PeptideCollection::assign_crowding_distance(&mut front);
+
+        // front is sorted by max_ratio into [1.0, 5.0, 10.0]; the two
+        // boundary members get infinite distance on every objective.
+        let by_ratio: HashMap<u32, f64> = front
+            .iter()
+            .map(|s| (s.max_ratio.to_bits(), s.crowding_distance))
+            .collect();
+        assert_eq!(by_ratio[&1.0f32.to_bits()], f64::INFINITY);
+        assert_eq!(by_ratio[&10.0f32.to_bits()], f64::INFINITY);
+        assert!(by_ratio[&5.0f32.to_bits()].is_finite());
+    }
+
+    #[test]
+    fn assign_crowding_distance_small_fronts_are_all_infinite() {
+        let mut front = vec![site(1.0, 1, 1), site(2.0, 1, 1)];
+        PeptideCollection::assign_crowding_distance(&mut front);
+        assert!(front.iter().all(|s| s.crowding_distance == f64::INFINITY));
+    }
+
+    fn collection(
+        peptides: HashMap<String, Vec<FilteredPeptide>>,
+        decoys: HashMap<String, Vec<FilteredPeptide>>,
+    ) -> PeptideCollection {
+        PeptideCollection {
+            peptides,
+            decoys,
+            experiments: vec![String::from("expt1")],
+        }
+    }
+
+    fn filtered(residue: Residue, ratio: f32) -> FilteredPeptide {
+        FilteredPeptide {
+            residue,
+            ms2: 1,
+            sequence: String::from("PEPTIDE"),
+            desc: String::new(),
+            ratios: vec![Some(ratio)],
+        }
+    }
+
+    #[test]
+    fn target_decoy_fdr_computes_ratio_and_monotone_q_value() {
+        let mut targets = HashMap::new();
+        targets.insert(
+            String::from("P1"),
+            vec![filtered(1, 10.0), filtered(2, 6.0), filtered(3, 3.0)],
+        );
+        let mut decoys = HashMap::new();
+        decoys.insert(
+            String::from("Reverse_P1"),
+            vec![filtered(1, 10.0), filtered(2, 3.0)],
+        );
+
+        let pc = collection(targets, decoys);
+
+        // At cutoff 8.0: 1 target (ratio 10.0), 1 decoy (ratio 10.0) -> fdr 1.0
+        // At cutoff 5.0: 2 targets (10.0, 6.0), 1 decoy (10.0) -> fdr 0.5
+        let points = pc.target_decoy_fdr(&[5.0, 8.0]);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].cutoff, 5.0);
+        assert_eq!(points[0].target_count, 2);
+        assert_eq!(points[0].decoy_count, 1);
+        assert_eq!(points[0].fdr, 0.5);
+
+        assert_eq!(points[1].cutoff, 8.0);
+        assert_eq!(points[1].target_count, 1);
+        assert_eq!(points[1].decoy_count, 1);
+        assert_eq!(points[1].fdr, 1.0);
+
+        // The raw fdr at the stricter cutoff (8.0, fdr 1.0) is worse than
+        // at the looser one (5.0, fdr 0.5), so the correction pulls 8.0's
+        // q_value down to match.
+        assert_eq!(points[0].q_value, 0.5);
+        assert_eq!(points[1].q_value, 0.5);
+    }
+
+    #[test]
+    fn target_decoy_fdr_zero_targets_is_zero_not_nan() {
+        let pc = collection(HashMap::new(), HashMap::new());
+        let points = pc.target_decoy_fdr(&[4.0]);
+        assert_eq!(points[0].target_count, 0);
+        assert_eq!(points[0].fdr, 0.0);
+    }
+
+    #[test]
+    fn q_value_by_cutoff_indexes_by_cutoff_bit_pattern() {
+        let points = vec![
+            FdrPoint {
+                cutoff: 4.0,
+                target_count: 10,
+                decoy_count: 1,
+                fdr: 0.1,
+                q_value: 0.1,
+            },
+            FdrPoint {
+                cutoff: 10.0,
+                target_count: 2,
+                decoy_count: 0,
+                fdr: 0.0,
+                q_value: 0.0,
+            },
+        ];
+        let indexed = PeptideCollection::q_value_by_cutoff(&points);
+        assert_eq!(indexed[&4.0f32.to_bits()], 0.1);
+        assert_eq!(indexed[&10.0f32.to_bits()], 0.0);
+    }
+}
@@ -0,0 +1,91 @@
+//! Opt-in amino-acid equivalence-class matching for mass-ambiguous residues.
+//!
+//! Isoleucine and leucine are indistinguishable by mass, and lysine and
+//! glutamine are nearly so, which means two peptide sequences that
+//! differ only at those positions can represent the same underlying
+//! identification. Exact `==`/[`str::contains`] comparisons (as used by
+//! [`crate::Protein::collapse_redundant_sites`] and
+//! [`crate::PeptideFilter::ExcludeMatch`]) treat them as distinct;
+//! [`AmbiguityMode`] and the functions here are an opt-in alternative
+//! that tolerate the configured equivalence classes instead.
+
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum AmbiguityMode {
+    /// I and L are treated as equivalent
+    IsoleucineLeucine,
+    /// I/L and K/Q are both treated as equivalent
+    Strict,
+}
+
+impl AmbiguityMode {
+    fn chars_equal(self, a: char, b: char) -> bool {
+        if a == b {
+            return true;
+        }
+        match self {
+            AmbiguityMode::IsoleucineLeucine => matches!((a, b), ('I', 'L') | ('L', 'I')),
+            AmbiguityMode::Strict => {
+                matches!((a, b), ('I', 'L') | ('L', 'I') | ('K', 'Q') | ('Q', 'K'))
+            }
+        }
+    }
+}
+
+/// Map every mass-ambiguous residue in `seq` onto a single representative
+/// (`I` -> `L`, and under [`AmbiguityMode::Strict`] also `Q` -> `K`), so
+/// that two sequences comparing equal under `mode` also normalize to the
+/// same `String` - needed anywhere the comparison result has to be used
+/// as a hashable grouping key, such as
+/// [`crate::Protein::collapse_redundant_sites_ambiguous`].
+pub fn normalize(seq: &str, mode: AmbiguityMode) -> String {
+    seq.chars()
+        .map(|c| match c {
+            'I' => 'L',
+            'Q' if mode == AmbiguityMode::Strict => 'K',
+            c => c,
+        })
+        .collect()
+}
+
+/// Walk `haystack` for an occurrence of `needle`, accepting a position
+/// when the characters are equal or form one of `mode`'s equivalence
+/// classes - the ambiguity-aware analogue of `haystack.contains(needle)`.
+pub fn contains(haystack: &str, needle: &str, mode: AmbiguityMode) -> bool {
+    let haystack: Vec<char> = haystack.chars().collect();
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() {
+        return true;
+    }
+    if needle.len() > haystack.len() {
+        return false;
+    }
+    haystack
+        .windows(needle.len())
+        .any(|w| w.iter().zip(needle.iter()).all(|(&a, &b)| mode.chars_equal(a, b)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn isoleucine_leucine_are_interchangeable() {
+        assert!(contains("PEPTIDE", "PEPTLDE", AmbiguityMode::IsoleucineLeucine));
+        assert!(!contains("PEPTIDE", "PEPTKDE", AmbiguityMode::IsoleucineLeucine));
+    }
+
+    #[test]
+    fn strict_mode_also_allows_k_q() {
+        assert!(contains("PEPTKDE", "PEPTQDE", AmbiguityMode::Strict));
+    }
+
+    #[test]
+    fn normalize_collapses_equivalence_classes() {
+        assert_eq!(normalize("ILIQ", AmbiguityMode::IsoleucineLeucine), "LLLQ");
+        assert_eq!(normalize("ILIQ", AmbiguityMode::Strict), "LLLK");
+    }
+}
@@ -0,0 +1,186 @@
+//! GO-term over-representation analysis.
+//!
+//! Turns the flat keyword/GO annotations loaded by [`uniprot::kw`] into
+//! a real functional-enrichment subsystem by propagating each protein's
+//! direct GO annotations up the [`uniprot::obo::GoDag`] and testing
+//! whether a foreground set of proteins (e.g. quantified or
+//! significantly-changing sites) is enriched for each term relative to
+//! a background set, using the hypergeometric test with
+//! Benjamini-Hochberg FDR correction.
+use super::*;
+use uniprot::kw::Annotation;
+use uniprot::obo::GoDag;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnrichmentResult {
+    pub term: String,
+    /// Number of foreground proteins annotated (directly or via
+    /// propagation) with this term
+    pub foreground_count: usize,
+    /// Number of background proteins annotated with this term
+    pub background_count: usize,
+    pub fold_enrichment: f64,
+    pub p_value: f64,
+    pub q_value: f64,
+}
+
+fn propagated_terms<'a, I: IntoIterator<Item = &'a String>>(
+    accessions: I,
+    ann: &Annotation,
+    dag: &GoDag,
+) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for acc in accessions {
+        for term in ann.propagate_go(acc, dag) {
+            *counts.entry(term).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Count occurrences of `term_of(acc)`'s semicolon-delimited terms across
+/// `accessions`, for annotation fields (like [`Annotation::keyword`]) that
+/// are stored as a single flat string rather than a DAG.
+fn flat_terms<'a, 'b, I: IntoIterator<Item = &'a String>>(
+    accessions: I,
+    term_of: impl Fn(&str) -> Option<&'b str>,
+) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for acc in accessions {
+        for term in term_of(acc).unwrap_or_default().split(';') {
+            let term = term.trim();
+            if !term.is_empty() {
+                *counts.entry(term.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Shared by [`go_enrichment`] and [`keyword_enrichment`]: given per-term
+/// counts already tallied across `foreground`/`background`, run the
+/// hypergeometric test against each foreground term and correct the
+/// resulting p-values with Benjamini-Hochberg.
+fn enrich_counted_terms(
+    foreground_terms: HashMap<String, usize>,
+    background_terms: &HashMap<String, usize>,
+    n: usize,
+    sample: usize,
+) -> Vec<EnrichmentResult> {
+    let mut rows = foreground_terms
+        .into_iter()
+        .map(|(term, fg_count)| {
+            let bg_count = background_terms.get(&term).copied().unwrap_or(0);
+            let p = stats::hypergeometric_sf(fg_count, bg_count, n, sample);
+            let expected = (bg_count as f64) * (sample as f64) / (n.max(1) as f64);
+            let fold = if expected > 0.0 {
+                fg_count as f64 / expected
+            } else {
+                0.0
+            };
+            (term, fg_count, bg_count, fold, p)
+        })
+        .collect::<Vec<_>>();
+
+    rows.sort_by(|a, b| a.4.partial_cmp(&b.4).unwrap());
+
+    let pvalues = rows.iter().map(|r| r.4).collect::<Vec<_>>();
+    let qvalues = stats::benjamini_hochberg(&pvalues);
+
+    rows.into_iter()
+        .zip(qvalues)
+        .map(
+            |((term, foreground_count, background_count, fold_enrichment, p_value), q_value)| {
+                EnrichmentResult {
+                    term,
+                    foreground_count,
+                    background_count,
+                    fold_enrichment,
+                    p_value,
+                    q_value,
+                }
+            },
+        )
+        .collect()
+}
+
+/// Compute GO term over-representation for `foreground` relative to
+/// `background` (which should be a superset of `foreground`,
+/// e.g. all quantified proteins in a [`Grouped`]/[`Aggregate`] dataset).
+///
+/// Results are returned sorted by ascending p-value.
+pub fn go_enrichment(
+    foreground: &HashSet<String>,
+    background: &HashSet<String>,
+    ann: &Annotation,
+    dag: &GoDag,
+) -> Vec<EnrichmentResult> {
+    let background_terms = propagated_terms(background, ann, dag);
+    let foreground_terms = propagated_terms(foreground, ann, dag);
+    enrich_counted_terms(
+        foreground_terms,
+        &background_terms,
+        background.len(),
+        foreground.len(),
+    )
+}
+
+/// Compute UniProt keyword over-representation for `foreground` relative
+/// to `background`, the same way [`go_enrichment`] does for GO terms but
+/// over [`Annotation::keyword`]'s flat, semicolon-delimited string instead
+/// of a propagated DAG.
+///
+/// Results are returned sorted by ascending p-value.
+pub fn keyword_enrichment(
+    foreground: &HashSet<String>,
+    background: &HashSet<String>,
+    ann: &Annotation,
+) -> Vec<EnrichmentResult> {
+    let background_terms = flat_terms(background, |acc| ann.keyword(acc));
+    let foreground_terms = flat_terms(foreground, |acc| ann.keyword(acc));
+    enrich_counted_terms(
+        foreground_terms,
+        &background_terms,
+        background.len(),
+        foreground.len(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enriches_term_present_only_in_foreground() {
+        let mut dag = GoDag::default();
+        dag.terms.insert(
+            String::from("GO:0000001"),
+            uniprot::obo::GoTerm {
+                id: String::from("GO:0000001"),
+                name: String::from("test term"),
+                namespace: uniprot::obo::Namespace::BiologicalProcess,
+                parents: Vec::new(),
+            },
+        );
+
+        let mut ann = Annotation::default();
+        ann.go_ids
+            .insert(String::from("P1"), vec![String::from("GO:0000001")]);
+        ann.go_ids
+            .insert(String::from("P2"), vec![String::from("GO:0000001")]);
+        ann.go_ids.insert(String::from("P3"), Vec::new());
+
+        let background = vec![String::from("P1"), String::from("P2"), String::from("P3")]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let foreground = vec![String::from("P1"), String::from("P2")]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let results = go_enrichment(&foreground, &background, &ann, &dag);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].term, "GO:0000001");
+        assert_eq!(results[0].foreground_count, 2);
+        assert_eq!(results[0].background_count, 2);
+    }
+}
@@ -0,0 +1,58 @@
+//! Protease cleavage-specificity modeling, for classifying peptide
+//! termini against a parent protein sequence instead of guessing from
+//! the peptide string alone.
+//!
+//! This mirrors how SEQUEST-style search engines describe a protease as
+//! a `SampleEnzyme`: a set of residues it cuts C-terminal to, and a set
+//! of residues that suppress that cut if they immediately follow it
+//! (trypsin cuts after K/R, except before P).
+
+use std::collections::HashSet;
+
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
+/// A protease's cleavage specificity: which residue(s) it cuts after,
+/// and which following residue(s) block that cut.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct Enzyme {
+    pub cut_after: HashSet<char>,
+    pub except_before: HashSet<char>,
+}
+
+impl Enzyme {
+    /// Cuts C-terminal to lysine or arginine, except when followed by proline
+    pub fn trypsin() -> Self {
+        Enzyme {
+            cut_after: ['K', 'R'].iter().copied().collect(),
+            except_before: ['P'].iter().copied().collect(),
+        }
+    }
+
+    /// Whether a cleavage between `before` and `after` is consistent
+    /// with this enzyme's specificity
+    pub fn cleaves(&self, before: char, after: char) -> bool {
+        self.cut_after.contains(&before) && !self.except_before.contains(&after)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trypsin_cleaves_after_k_and_r() {
+        let enzyme = Enzyme::trypsin();
+        assert!(enzyme.cleaves('K', 'A'));
+        assert!(enzyme.cleaves('R', 'A'));
+        assert!(!enzyme.cleaves('A', 'K'));
+    }
+
+    #[test]
+    fn trypsin_does_not_cleave_before_proline() {
+        let enzyme = Enzyme::trypsin();
+        assert!(!enzyme.cleaves('K', 'P'));
+        assert!(!enzyme.cleaves('R', 'P'));
+    }
+}
@@ -1,10 +1,24 @@
 use super::*;
 
+#[cfg(feature = "serialization")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum ProteinFilter {
     Reverse,
     Spectra(usize),
     ExcludeMatch(String),
+    /// Keep only proteins with at least this many distinct peptide
+    /// sequences, complementing [`ProteinFilter::Spectra`]'s count of
+    /// quantified ratios.
+    SequenceCounts(usize),
+    /// Pass if any of the wrapped filters pass
+    Any(Vec<ProteinFilter>),
+    /// Pass if all of the wrapped filters pass
+    All(Vec<ProteinFilter>),
+    /// Pass if the wrapped filter does not
+    Not(Box<ProteinFilter>),
 }
 
 /// A collection of information about a set of ratios
@@ -45,14 +59,31 @@ impl SecondPassFilter {
 #[derive(Clone)]
 pub enum PeptideFilter<'s> {
     HalfTryptic,
+    /// Like `HalfTryptic`, but classifies termini by actually locating
+    /// the peptide within its parent protein sequence (see
+    /// [`crate::Grouped::load_fasta`]) and checking both ends against
+    /// `enzyme`'s cleavage rules, rather than guessing from the
+    /// peptide string's own flanking residues.
+    HalfTrypticEnzyme(&'s HashMap<String, String>, Enzyme),
     ExcludeMatch(&'s str),
+    /// Like `ExcludeMatch`, but tolerates mass-ambiguous residues (I/L,
+    /// and under [`AmbiguityMode::Strict`] K/Q) instead of comparing
+    /// sequences byte-for-byte. See [`crate::ambiguity`].
+    ExcludeMatchAmbiguous(&'s str, AmbiguityMode),
     Ratios(RatioFilter),
     Ms2(usize),
     // SPF2(&'s HashMap<String, HashMap<Residue, SecondPassFilter>>),
     SecondPassFilter(&'s PeptideCollection, &'s HashMap<String, String>, String),
+    /// Pass if any of the wrapped filters pass
+    Any(Vec<PeptideFilter<'s>>),
+    /// Pass if all of the wrapped filters pass
+    All(Vec<PeptideFilter<'s>>),
+    /// Pass if the wrapped filter does not
+    Not(Box<PeptideFilter<'s>>),
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum RatioFilter {
     /// Filter out 20s from sets of ratios with high stdev
     CV(f32),
@@ -61,6 +92,89 @@ pub enum RatioFilter {
     Spurious,
 }
 
+/// An owned, serializable mirror of [`PeptideFilter`].
+///
+/// `PeptideFilter` borrows (`ExcludeMatch(&'s str)`) and, for
+/// `SecondPassFilter`, carries live references to a `PeptideCollection`
+/// and chemotype map that only exist once an analysis is running, so it
+/// can't derive `Serialize`/`Deserialize` itself. `PeptideFilterSpec`
+/// holds `String` instead and records only the experiment name for the
+/// second-pass filter; [`Filter::from_spec`] rebinds it against the
+/// caller's `PeptideCollection`/chemotype map when loading a config.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum PeptideFilterSpec {
+    HalfTryptic,
+    /// Rebound against `sequences` by [`Filter::from_spec`]
+    HalfTrypticEnzyme(Enzyme),
+    ExcludeMatch(String),
+    ExcludeMatchAmbiguous(String, AmbiguityMode),
+    Ratios(RatioFilter),
+    Ms2(usize),
+    /// Name of the experiment to pass through to `PeptideFilter::SecondPassFilter`
+    /// once rebound with a live `PeptideCollection`/chemotype map
+    SecondPassFilter(String),
+    Any(Vec<PeptideFilterSpec>),
+    All(Vec<PeptideFilterSpec>),
+    Not(Box<PeptideFilterSpec>),
+}
+
+/// An owned, serializable mirror of [`Filter`], suitable for saving and
+/// reloading a whole filter pipeline from a config file. See
+/// [`PeptideFilterSpec`] for why the peptide filters need a separate
+/// owned representation, and [`Filter::from_spec`] to turn this back
+/// into a runnable [`Filter`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct FilterSpec {
+    pub protein_filters: Vec<ProteinFilter>,
+    pub peptide_filters: Vec<PeptideFilterSpec>,
+}
+
+/// Rebind a single [`PeptideFilterSpec`] into a runnable [`PeptideFilter`],
+/// recursing into `Any`/`All`/`Not` so a whole combinator tree can be
+/// rebound in one pass. See [`Filter::from_spec`].
+fn bind_peptide_filter<'a>(
+    spec: &'a PeptideFilterSpec,
+    collection: &'a PeptideCollection,
+    chemotype_map: &'a HashMap<String, String>,
+    sequences: &'a HashMap<String, String>,
+) -> PeptideFilter<'a> {
+    match spec {
+        PeptideFilterSpec::HalfTryptic => PeptideFilter::HalfTryptic,
+        PeptideFilterSpec::HalfTrypticEnzyme(enzyme) => {
+            PeptideFilter::HalfTrypticEnzyme(sequences, enzyme.clone())
+        }
+        PeptideFilterSpec::ExcludeMatch(s) => PeptideFilter::ExcludeMatch(s.as_str()),
+        PeptideFilterSpec::ExcludeMatchAmbiguous(s, mode) => {
+            PeptideFilter::ExcludeMatchAmbiguous(s.as_str(), *mode)
+        }
+        PeptideFilterSpec::Ratios(rf) => PeptideFilter::Ratios(rf.clone()),
+        PeptideFilterSpec::Ms2(cutoff) => PeptideFilter::Ms2(*cutoff),
+        PeptideFilterSpec::SecondPassFilter(expt) => {
+            PeptideFilter::SecondPassFilter(collection, chemotype_map, expt.clone())
+        }
+        PeptideFilterSpec::Any(specs) => PeptideFilter::Any(
+            specs
+                .iter()
+                .map(|s| bind_peptide_filter(s, collection, chemotype_map, sequences))
+                .collect(),
+        ),
+        PeptideFilterSpec::All(specs) => PeptideFilter::All(
+            specs
+                .iter()
+                .map(|s| bind_peptide_filter(s, collection, chemotype_map, sequences))
+                .collect(),
+        ),
+        PeptideFilterSpec::Not(spec) => PeptideFilter::Not(Box::new(bind_peptide_filter(
+            spec,
+            collection,
+            chemotype_map,
+            sequences,
+        ))),
+    }
+}
+
 #[derive(Clone)]
 pub struct Filter<'a> {
     peptide_filters: Vec<PeptideFilter<'a>>,
@@ -85,147 +199,43 @@ impl<'a> Filter<'a> {
         self
     }
 
-    pub fn filter(&self, protein: Protein) -> Option<Protein> {
-        use PeptideFilter::*;
-        use RatioFilter::*;
+    /// Rebuild a runnable [`Filter`] from a serialized [`FilterSpec`],
+    /// binding its `SecondPassFilter` experiment names against a live
+    /// `collection`/`chemotype_map`, and its `HalfTrypticEnzyme` filter
+    /// against a live parent-sequence map (see [`crate::Grouped::sequences`])
+    /// - none of which exist until an analysis is underway.
+    pub fn from_spec(
+        spec: &'a FilterSpec,
+        collection: &'a PeptideCollection,
+        chemotype_map: &'a HashMap<String, String>,
+        sequences: &'a HashMap<String, String>,
+    ) -> Self {
+        let mut filter = Filter::new();
+        for pf in &spec.protein_filters {
+            filter = filter.add_protein_filter(pf.clone());
+        }
+        for pf in &spec.peptide_filters {
+            let pf = bind_peptide_filter(pf, collection, chemotype_map, sequences);
+            filter = filter.add_peptide_filter(pf);
+        }
+        filter
+    }
 
+    pub fn filter(&self, protein: Protein) -> Option<Protein> {
         let mut filtered = Vec::new();
 
-        // Check for reverse protein ID filter first - no need to spend
-        // time filtering peptides if we have reverse accession
-        for filter in &self.protein_filters {
-            if let ProteinFilter::Reverse = filter {}
-            match filter {
-                ProteinFilter::Reverse => {
-                    if protein.accession.contains("Reverse") {
-                        return None;
-                    }
-                }
-                ProteinFilter::ExcludeMatch(s) => {
-                    if protein.description.contains(s) {
-                        return None;
-                    }
-                }
-                _ => {}
-            }
-        }
-        let mut trigger = false;
+        let trigger = false;
         if !self.peptide_filters.is_empty() {
             for mut peptide in protein.peptides {
-                let mut pass = true;
                 if peptide.ratios.iter().all(|x| x.is_none()) {
                     // Don't need to filter an empty peptide
                     continue;
                 }
-                // if trigger &&  peptide.non_zeroes().len() < 2 {
-                //     pass = false;
-                //     break;
-                // }
-                for filter in &self.peptide_filters {
-                    match filter {
-                        HalfTryptic => {
-                            if !peptide.is_not_half_tryptic() {
-                                pass = false;
-                                break;
-                            }
-                        }
-                        ExcludeMatch(seq) => {
-                            if peptide.sequence.contains(seq) {
-                                pass = false;
-                                break;
-                            }
-                        }
-                        Ratios(rf) => match rf {
-                            CV(cv) => peptide.cv_filter(*cv),
-                            Count(count) => {
-                                if peptide.non_zeroes().len() < *count {
-                                    pass = false;
-                                    break;
-                                }
-                            }
-
-                            Spurious => peptide.spurious_filter(),
-                        },
-                        Ms2(cutoff) => {
-                            if peptide.ms2 < *cutoff {
-                                // For now, just remove twenties
-                                peptide.remove_twenties();
-                            }
-                        }
-                        SecondPassFilter(pc, chemotype_map, expt) => {
-                            // Ok this is funky.. but the second pass filter should contain
-                            // all of the information about the count of liganded, twenties,
-                            // and non-liganded ratios for all other compounds within the
-                            // same chemotype as [`expt`].
-                            let spf = pc.second_pass_filter(
-                                expt,
-                                chemotype_map,
-                                &protein.accession,
-                                peptide.residue,
-                            );
-                            let this = filter::SecondPassFilter::from_ratios(&peptide.ratios);
-
-                            // remove cases where for this compound, all of the reported
-                            // replicates have a median ratio of 20, but there are no non-
-                            // 20's in any other compound aggregate set
-                            //
-                            // Modified to be as stated:
-                            //
-                            // If all ratios within a set of replicates for a
-                            // given compound ([`expt`]) are 20s, and no other
-                            // compound within our chemotype has a single
-                            // ligand event for this peptide, then we remove
-                            // 20s
-                            if spf.liganded == 0 {
-                                peptide.remove_twenties();
-                            }
-
-                            // if this.twenties == 1 && this.liganded == 0 {
-                            //     peptide.remove_twenties();
-                            // }
-
-                            if spf.liganded == 1 && this.twenties == 1 && this.liganded == 0 {
-                                peptide.remove_twenties();
-                            }
-
-                            // Remake our second pass filter, this time
-                            // collecting ratios from the *entire* dataset, not
-                            // just our chemotype group. We use this to ensure
-                            // that every ligandable ratio is quantified somewhere
-                            // else in the dataset
-                            // let spf = pc.second_pass_filter(
-                            //     expt,
-                            //     chemotype_map,
-                            //     &protein.accession,
-                            //     peptide.residue,
-                            //     FilterScope::Dataset,
-                            // );
-
-                            // "I’d also recommend that we require for a site to
-                            // be quantified in at least 5 distinct data sets for
-                            // interpretation – this will remove some events with
-                            // very sparse coverage where the single ligandability
-                            // event is also borderline quality (we could remove these
-                            // events manually too, but I didn’t notice many convincing
-                            // liganding events for sites with very sparse coverage; e.g.,
-                            // fewer than 5 quantification events across the entire data set"
-                            if spf.total() < 5 && !trigger {
-                                pass = false;
-                                if trigger {
-                                    // eprintln!("5 {:?}", peptide);
-                                }
-                                break;
-                            }
-                        }
-                    }
-                }
+                let pass = self.peptide_filters.iter().all(|filter| {
+                    eval_peptide_filter(filter, &protein.accession, &mut peptide, trigger)
+                });
                 if pass {
                     filtered.push(peptide);
-                } else {
-                    // eprintln!("dropping peptide");
-                    if trigger {
-                        // eprintln!("drop {:?}", peptide);
-                    }
                 }
             }
         } else {
@@ -243,21 +253,127 @@ impl<'a> Filter<'a> {
         constructed.description = protein.description;
         // constructed.map = constructed.peptides.iter().enumerate().map(|(idx, p)| (p.residue, idx)).collect();
 
-        for filter in &self.protein_filters {
-            match filter {
-                ProteinFilter::Spectra(count) => {
-                    if constructed.spectral_counts() < *count {
-                        return None;
-                    }
-                }
-                _ => {}
-            }
+        let passes = self
+            .protein_filters
+            .iter()
+            .all(|filter| eval_protein_filter(filter, &constructed));
+        if !passes {
+            return None;
         }
 
         Some(constructed)
     }
 }
 
+/// Evaluate a single peptide-level filter against `peptide`, applying any
+/// mutating side effects (e.g. [`Peptide::remove_twenties`]) along the
+/// way, and return whether the peptide still passes. Recurses into
+/// `Any`/`All`/`Not` so a combinator can wrap any other peptide filter,
+/// including another combinator.
+fn eval_peptide_filter(
+    filter: &PeptideFilter<'_>,
+    accession: &str,
+    peptide: &mut Peptide,
+    trigger: bool,
+) -> bool {
+    use PeptideFilter::*;
+    use RatioFilter::*;
+
+    match filter {
+        HalfTryptic => peptide.is_not_half_tryptic(),
+        HalfTrypticEnzyme(sequences, enzyme) => sequences
+            .get(accession)
+            .map(|seq| peptide.is_not_half_tryptic_against(seq, enzyme))
+            .unwrap_or(false),
+        ExcludeMatch(seq) => !peptide.sequence.contains(seq),
+        ExcludeMatchAmbiguous(seq, mode) => !ambiguity::contains(&peptide.sequence, seq, *mode),
+        Ratios(rf) => match rf {
+            CV(cv) => {
+                peptide.cv_filter(*cv);
+                true
+            }
+            Count(count) => peptide.non_zeroes().len() >= *count,
+            Spurious => {
+                peptide.spurious_filter();
+                true
+            }
+        },
+        Ms2(cutoff) => {
+            if peptide.ms2 < *cutoff {
+                // For now, just remove twenties
+                peptide.remove_twenties();
+            }
+            true
+        }
+        SecondPassFilter(pc, chemotype_map, expt) => {
+            // Ok this is funky.. but the second pass filter should contain
+            // all of the information about the count of liganded, twenties,
+            // and non-liganded ratios for all other compounds within the
+            // same chemotype as [`expt`].
+            let spf = pc.second_pass_filter(expt, chemotype_map, accession, peptide.residue);
+            let this = filter::SecondPassFilter::from_ratios(&peptide.ratios);
+
+            // remove cases where for this compound, all of the reported
+            // replicates have a median ratio of 20, but there are no non-
+            // 20's in any other compound aggregate set
+            //
+            // Modified to be as stated:
+            //
+            // If all ratios within a set of replicates for a
+            // given compound ([`expt`]) are 20s, and no other
+            // compound within our chemotype has a single
+            // ligand event for this peptide, then we remove
+            // 20s
+            if spf.liganded == 0 {
+                peptide.remove_twenties();
+            }
+
+            if spf.liganded == 1 && this.twenties == 1 && this.liganded == 0 {
+                peptide.remove_twenties();
+            }
+
+            // "I’d also recommend that we require for a site to
+            // be quantified in at least 5 distinct data sets for
+            // interpretation – this will remove some events with
+            // very sparse coverage where the single ligandability
+            // event is also borderline quality (we could remove these
+            // events manually too, but I didn’t notice many convincing
+            // liganding events for sites with very sparse coverage; e.g.,
+            // fewer than 5 quantification events across the entire data set"
+            !(spf.total() < 5 && !trigger)
+        }
+        Any(filters) => filters
+            .iter()
+            .any(|f| eval_peptide_filter(f, accession, peptide, trigger)),
+        All(filters) => filters
+            .iter()
+            .all(|f| eval_peptide_filter(f, accession, peptide, trigger)),
+        Not(f) => !eval_peptide_filter(f, accession, peptide, trigger),
+    }
+}
+
+/// Evaluate a single protein-level filter against the already
+/// peptide-filtered `protein`, recursing into `Any`/`All`/`Not`.
+fn eval_protein_filter(filter: &ProteinFilter, protein: &Protein) -> bool {
+    match filter {
+        ProteinFilter::Reverse => !protein.accession.contains("Reverse"),
+        ProteinFilter::ExcludeMatch(s) => !protein.description.contains(s),
+        ProteinFilter::Spectra(count) => protein.spectral_counts() >= *count,
+        ProteinFilter::SequenceCounts(count) => {
+            protein
+                .peptides
+                .iter()
+                .map(|p| p.sequence.as_str())
+                .collect::<HashSet<_>>()
+                .len()
+                >= *count
+        }
+        ProteinFilter::Any(filters) => filters.iter().any(|f| eval_protein_filter(f, protein)),
+        ProteinFilter::All(filters) => filters.iter().all(|f| eval_protein_filter(f, protein)),
+        ProteinFilter::Not(f) => !eval_protein_filter(f, protein),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::PeptideFilter::*;
@@ -283,16 +399,16 @@ mod test {
         prot.add_peptide(pepa);
         prot.add_peptide(pepb);
 
-        let a = prot.get(0).unwrap();
-        let b = prot.get(1).unwrap();
+        let a = prot.get(0, &[]).unwrap();
+        let b = prot.get(1, &[]).unwrap();
 
         let filter = Filter::new()
             .add_peptide_filter(Ratios(CV(0.6)))
             .add_peptide_filter(Ratios(Spurious));
 
         let f = filter.filter(prot).unwrap();
-        let a = f.get(0).unwrap();
-        let b = f.get(1).unwrap();
+        let a = f.get(0, &[]).unwrap();
+        let b = f.get(1, &[]).unwrap();
 
         assert_eq!(a.ratios.iter().filter(|&r| *r == Some(20.0)).count(), 0);
         assert_eq!(b.ratios.iter().filter(|&r| *r == Some(20.0)).count(), 1);
@@ -310,4 +426,121 @@ mod test {
             None
         );
     }
+
+    #[test]
+    fn sequence_counts_requires_minimum_distinct_peptides() {
+        let mut pepa = Peptide::default();
+        pepa.sequence = String::from("A");
+        pepa.residue = 0;
+        pepa.ratios.push(Some(1.0));
+
+        let mut prot = Protein::default();
+        prot.add_peptide(pepa);
+
+        let filter = Filter::new().add_protein_filter(ProteinFilter::SequenceCounts(2));
+        assert_eq!(filter.filter(prot), None);
+    }
+
+    #[test]
+    fn not_combinator_inverts_reverse_filter() {
+        // Not(Reverse) inverts the usual "drop reverse hits" meaning into
+        // "keep only reverse hits"
+        let filter = Filter::new()
+            .add_protein_filter(ProteinFilter::Not(Box::new(ProteinFilter::Reverse)));
+
+        let mut pepa = Peptide::default();
+        pepa.sequence = String::from("A");
+        pepa.residue = 0;
+        pepa.ratios.push(Some(1.0));
+
+        let mut reverse = Protein::new(String::from("Reverse_QQQQ"), String::default());
+        reverse.add_peptide(pepa.clone());
+        assert!(filter.filter(reverse).is_some());
+
+        let mut forward = Protein::new(String::from("Q1"), String::default());
+        forward.add_peptide(pepa);
+        assert_eq!(filter.filter(forward), None);
+    }
+
+    #[test]
+    fn from_spec_rebinds_nested_combinators() {
+        // A nested FilterSpec exercising Any/All/Not on both the protein
+        // and peptide sides, round-tripped through Filter::from_spec the
+        // way a deserialized spec would be.
+        let spec = FilterSpec {
+            protein_filters: vec![ProteinFilter::All(vec![
+                ProteinFilter::Reverse,
+                ProteinFilter::Not(Box::new(ProteinFilter::SequenceCounts(5))),
+            ])],
+            peptide_filters: vec![PeptideFilterSpec::Any(vec![
+                PeptideFilterSpec::ExcludeMatch(String::from("ZZZ")),
+                PeptideFilterSpec::ExcludeMatch(String::from("K.K*")),
+            ])],
+        };
+
+        let collection = PeptideCollection::new(&Aggregate::default());
+        let chemotype_map = HashMap::new();
+        let sequences = HashMap::new();
+        let filter = Filter::from_spec(&spec, &collection, &chemotype_map, &sequences);
+
+        // Contains both excluded motifs, so every branch of the Any fails
+        let mut dropped = Peptide::default();
+        dropped.sequence = String::from("ZZZK.K*LL.R");
+        dropped.residue = 0;
+        dropped.ratios.push(Some(1.0));
+
+        let mut prot = Protein::new(String::from("Q1"), String::default());
+        prot.add_peptide(dropped);
+        assert_eq!(filter.filter(prot), None);
+
+        // Only contains one of the two excluded motifs (so the peptide Any
+        // passes) on a forward accession with a single peptide (so both
+        // Reverse and Not(SequenceCounts(5)) pass), so the whole chain keeps it
+        let mut kept = Peptide::default();
+        kept.sequence = String::from("K.K*LL.R");
+        kept.residue = 0;
+        kept.ratios.push(Some(1.0));
+
+        let mut prot = Protein::new(String::from("Q1"), String::default());
+        prot.add_peptide(kept);
+        assert!(filter.filter(prot).is_some());
+
+        // Same peptide, but on a reverse hit: the protein-level Reverse branch fails
+        let mut reverse_kept = Peptide::default();
+        reverse_kept.sequence = String::from("K.K*LL.R");
+        reverse_kept.residue = 0;
+        reverse_kept.ratios.push(Some(1.0));
+
+        let mut prot = Protein::new(String::from("Reverse_Q1"), String::default());
+        prot.add_peptide(reverse_kept);
+        assert_eq!(filter.filter(prot), None);
+    }
+
+    #[test]
+    fn any_peptide_combinator_fails_only_if_every_branch_fails() {
+        let filter = Filter::new().add_peptide_filter(Any(vec![
+            ExcludeMatch("ZZZ"),
+            ExcludeMatch("K.K*"),
+        ]));
+
+        // Contains both excluded motifs, so every branch of the Any fails
+        let mut dropped = Peptide::default();
+        dropped.sequence = String::from("ZZZK.K*LL.R");
+        dropped.residue = 0;
+        dropped.ratios.push(Some(1.0));
+
+        let mut prot = Protein::default();
+        prot.add_peptide(dropped);
+        assert_eq!(filter.filter(prot), None);
+
+        // Only contains one of the two excluded motifs, so the Any still passes
+        let mut kept = Peptide::default();
+        kept.sequence = String::from("K.K*LL.R");
+        kept.residue = 0;
+        kept.ratios.push(Some(1.0));
+
+        let mut prot = Protein::default();
+        prot.add_peptide(kept);
+        assert!(filter.filter(prot).is_some());
+    }
 }
@@ -0,0 +1,578 @@
+//! Parsers for community-standard peptide identification formats
+//! (mzIdentML and pepXML), feeding the same [`Protein`]/[`Peptide`]
+//! structures built by the CIMAGE-native [`crate::parser`] module.
+//!
+//! Both formats are read with a pull parser (`quick_xml`) so that a
+//! multi-gigabyte search-engine export never has to be resident in
+//! memory as a single string or DOM tree.
+use super::*;
+use crate::modification::localize_mods;
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use std::io::BufReader;
+use uniprot::fasta::Fasta;
+use uniprot::Entry;
+
+/// A single localized modification, as reported by the search engine
+struct ModSite {
+    /// 1-based residue position within the peptide
+    location: usize,
+    /// `monoisotopicMassDelta`, if the document reported one; `0.0`
+    /// otherwise (a named modification fills this in via `name` once a
+    /// `cvParam` child is parsed).
+    mass: f64,
+    /// The modification's name, if given by a `cvParam` child (e.g.
+    /// `Carbamidomethyl`). Falls back to the mass delta when registering
+    /// into a [`ModTable`], matching [`crate::modification::parse_proforma`]'s
+    /// bare-mass-delta convention.
+    name: Option<String>,
+}
+
+/// Peptide sequence + modification sites, keyed by the identifier the
+/// format uses to refer back to it (mzIdentML `Peptide/@id`, pepXML
+/// re-derives this per `spectrum_query` so it's assigned inline)
+#[derive(Default)]
+struct PeptideDef {
+    sequence: String,
+    mods: Vec<ModSite>,
+}
+
+/// A protein accession + description, keyed by the format's internal id
+/// (mzIdentML `DBSequence/@id`)
+#[derive(Default)]
+struct ProteinDef {
+    accession: String,
+    description: String,
+}
+
+/// Mark the first modification site with a `*`, for `Peptide::sequence`'s
+/// single-marker display convention. The full modification set (beyond
+/// just this first site) is localized separately via
+/// [`crate::modification::localize_mods`] and carried on `Peptide::mods`.
+fn apply_mods(def: &PeptideDef) -> (String, Residue) {
+    match def.mods.iter().map(|m| m.location).min() {
+        Some(loc) => {
+            let mut seq = def.sequence.clone();
+            // location is 1-based and counts the residue being modified
+            let byte_idx = loc.saturating_sub(1).min(seq.len());
+            seq.insert(byte_idx, '*');
+            (seq, loc as Residue)
+        }
+        None => (def.sequence.clone(), 0),
+    }
+}
+
+/// Pull a scan number out of an mzIdentML `spectrumID` attribute. Most
+/// search engines encode it as a `scan=1234` token (e.g. Comet/MSGF+'s
+/// `controllerType=0 controllerNumber=1 scan=1234`); fall back to the
+/// trailing run of digits for engines that just use a bare native ID.
+fn extract_scan_id(spectrum_id: &str) -> Option<ScanId> {
+    if let Some(pos) = spectrum_id.find("scan=") {
+        let rest = &spectrum_id[pos + "scan=".len()..];
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if let Ok(scan) = digits.parse() {
+            return Some(scan);
+        }
+    }
+    let digits: String = spectrum_id
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    digits.parse().ok()
+}
+
+/// Localize `raw_mods` (peptide-relative 0-based offsets) against
+/// `accession`'s real sequence in `fasta` via [`localize_mods`], deriving
+/// `residue` from that *same* localized position so it never disagrees
+/// with the returned mods about which coordinate system they're in - both
+/// are protein-absolute, matching [`Protein::map`]'s site key. Falls back
+/// to `peptide_residue` (from [`apply_mods`]) and the unlocalized
+/// `raw_mods` when `accession` isn't in `fasta` or the peptide doesn't
+/// match its sequence.
+fn localize_or_fallback(
+    fasta: &Fasta,
+    accession: &str,
+    peptide_sequence: &str,
+    raw_mods: &[(usize, ModId)],
+    peptide_residue: Residue,
+) -> (Residue, Vec<(usize, ModId)>) {
+    fasta
+        .sequence(accession)
+        .and_then(|protein_seq| {
+            let entry = Entry {
+                accession: accession.to_string(),
+                identifier: String::new(),
+                sequence: protein_seq.clone(),
+            };
+            localize_mods(&entry, peptide_sequence, raw_mods)
+                .into_iter()
+                .next()
+        })
+        .map(|local_mods| {
+            let residue = local_mods
+                .iter()
+                .map(|&(pos, _)| pos)
+                .min()
+                .map(|pos| pos as Residue)
+                .unwrap_or(peptide_residue);
+            (residue, local_mods)
+        })
+        .unwrap_or_else(|| (peptide_residue, raw_mods.to_vec()))
+}
+
+fn xml_attr(e: &quick_xml::events::BytesStart, key: &str) -> Option<String> {
+    e.attributes().filter_map(|a| a.ok()).find_map(|a| {
+        if a.key.as_ref() == key.as_bytes() {
+            Some(String::from_utf8_lossy(&a.value).into_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse an mzIdentML (`.mzid`) document into a [`Grouped`] dataset.
+///
+/// This streams `SpectrumIdentificationItem`/`PeptideEvidence` entries
+/// and joins them against the `Peptide`/`DBSequence` elements collected
+/// along the way, so only the (typically much smaller) id -> metadata
+/// maps are held in memory rather than the whole document.
+///
+/// `fasta` supplies each hit protein's sequence so a peptide's named
+/// modifications (see [`crate::modification`]) can be localized to real
+/// protein positions via [`localize_mods`], the same way `Raw::load`
+/// uses a [`Fasta`] to localize the CIMAGE-native single-probe site.
+pub fn load_mzid<P: AsRef<Path>>(path: P, fasta: &Fasta) -> io::Result<Grouped> {
+    let path = path.as_ref();
+    let file = fs::File::open(path)?;
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+
+    let mut peptides: HashMap<String, PeptideDef> = HashMap::new();
+    let mut proteins: HashMap<String, ProteinDef> = HashMap::new();
+    // PeptideEvidence id -> (peptide_id, dbsequence_id)
+    let mut evidence: HashMap<String, (String, String)> = HashMap::new();
+    let mut mod_table = ModTable::new();
+
+    let mut buf = Vec::new();
+    let mut cur_peptide: Option<(String, PeptideDef)> = None;
+    let mut cur_sii: Option<(String, Vec<String>, Option<f32>)> = None;
+    let mut cur_spectrum_id: Option<String> = None;
+
+    let mut table: HashMap<String, Protein> = HashMap::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(XmlEvent::Start(ref e)) | Ok(XmlEvent::Empty(ref e)) => {
+                match e.name().as_ref() {
+                    b"Peptide" => {
+                        if let Some(id) = xml_attr(e, "id") {
+                            cur_peptide = Some((id, PeptideDef::default()));
+                        }
+                    }
+                    b"PeptideSequence" => {}
+                    b"Modification" => {
+                        if let Some((_, ref mut def)) = cur_peptide {
+                            if let Some(loc) = xml_attr(e, "location").and_then(|s| s.parse().ok())
+                            {
+                                let mass = xml_attr(e, "monoisotopicMassDelta")
+                                    .and_then(|s| s.parse().ok())
+                                    .unwrap_or(0.0);
+                                def.mods.push(ModSite {
+                                    location: loc,
+                                    mass,
+                                    name: None,
+                                });
+                            }
+                        }
+                    }
+                    b"DBSequence" => {
+                        if let Some(id) = xml_attr(e, "id") {
+                            let accession = xml_attr(e, "accession").unwrap_or_default();
+                            proteins.insert(
+                                id,
+                                ProteinDef {
+                                    accession,
+                                    description: String::new(),
+                                },
+                            );
+                        }
+                    }
+                    b"PeptideEvidence" => {
+                        if let (Some(id), Some(peptide_ref), Some(dbseq_ref)) = (
+                            xml_attr(e, "id"),
+                            xml_attr(e, "peptide_ref"),
+                            xml_attr(e, "dBSequence_ref"),
+                        ) {
+                            evidence.insert(id, (peptide_ref, dbseq_ref));
+                        }
+                    }
+                    b"SpectrumIdentificationResult" => {
+                        cur_spectrum_id = xml_attr(e, "spectrumID");
+                    }
+                    b"SpectrumIdentificationItem" => {
+                        if let Some(peptide_ref) = xml_attr(e, "peptide_ref") {
+                            cur_sii = Some((peptide_ref, Vec::new(), None));
+                        }
+                    }
+                    b"PeptideEvidenceRef" => {
+                        if let (Some((_, ref mut refs, _)), Some(ev_ref)) =
+                            (cur_sii.as_mut(), xml_attr(e, "peptideEvidence_ref"))
+                        {
+                            refs.push(ev_ref);
+                        }
+                    }
+                    b"cvParam" => {
+                        if let Some((_, _, ref mut score)) = cur_sii {
+                            // Search engine score cvParams (e.g. MS-GF+'s
+                            // "MS-GF:SpecEValue", Comet's "Comet:xcorr")
+                            // vary by engine, but all report a numeric
+                            // `value` attribute directly on the SII - take
+                            // the first one we see rather than chasing a
+                            // specific accession.
+                            if score.is_none() {
+                                *score = xml_attr(e, "value").and_then(|v| v.parse::<f32>().ok());
+                            }
+                        } else if let Some((_, ref mut def)) = cur_peptide {
+                            // A Modification's cvParam child names it (e.g.
+                            // "Carbamidomethyl") - attach it to whichever
+                            // ModSite we just pushed.
+                            if let Some(site) = def.mods.last_mut() {
+                                if site.name.is_none() {
+                                    site.name = xml_attr(e, "name");
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(XmlEvent::End(ref e)) => match e.name().as_ref() {
+                b"Peptide" => {
+                    if let Some((id, mut def)) = cur_peptide.take() {
+                        if def.sequence.is_empty() {
+                            // PeptideSequence text was read separately below
+                        }
+                        peptides.insert(id, std::mem::take(&mut def));
+                    }
+                }
+                b"SpectrumIdentificationItem" => {
+                    if let Some((peptide_ref, evidence_refs, score)) = cur_sii.take() {
+                        if let Some(def) = peptides.get(&peptide_ref) {
+                            let (seq, peptide_residue) = apply_mods(def);
+                            let raw_mods: Vec<(usize, ModId)> = def
+                                .mods
+                                .iter()
+                                .map(|m| {
+                                    let name = m
+                                        .name
+                                        .clone()
+                                        .unwrap_or_else(|| format!("{:+}", m.mass));
+                                    (m.location.saturating_sub(1), mod_table.register(&name, m.mass))
+                                })
+                                .collect();
+                            let spectra = match cur_spectrum_id.as_deref().and_then(extract_scan_id) {
+                                Some(scan) => SpectrumIds::FileUnknown(vec![scan]),
+                                None => SpectrumIds::None,
+                            };
+                            for ev_ref in &evidence_refs {
+                                if let Some((_, dbseq_ref)) = evidence.get(ev_ref) {
+                                    if let Some(prot) = proteins.get(dbseq_ref) {
+                                        let (residue, mods) = localize_or_fallback(
+                                            fasta,
+                                            &prot.accession,
+                                            &def.sequence,
+                                            &raw_mods,
+                                            peptide_residue,
+                                        );
+                                        table
+                                            .entry(prot.accession.clone())
+                                            .or_insert_with(|| {
+                                                Protein::new(
+                                                    prot.accession.clone(),
+                                                    prot.description.clone(),
+                                                )
+                                            })
+                                            .add_ratio(residue, &seq, None, 1, spectra.clone(), score, &mods);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                b"SpectrumIdentificationResult" => {
+                    cur_spectrum_id = None;
+                }
+                _ => {}
+            },
+            Ok(XmlEvent::Text(e)) => {
+                if let Some((_, ref mut def)) = cur_peptide {
+                    if def.sequence.is_empty() {
+                        def.sequence = e.unescape().unwrap_or_default().into_owned();
+                    }
+                }
+            }
+            Ok(XmlEvent::Eof) => break,
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed mzIdentML at position {}: {}", reader.buffer_position(), e),
+                ))
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(Grouped {
+        proteins: table,
+        path: path.file_name().unwrap().to_str().unwrap().to_string(),
+        sequences: HashMap::new(),
+    })
+}
+
+/// Parse a pepXML document into a [`Grouped`] dataset.
+///
+/// pepXML inlines protein/peptide information directly on each
+/// `search_hit`, so unlike mzIdentML there's no separate id table to
+/// join against - each `spectrum_query` is handled as it's encountered.
+///
+/// `fasta` supplies each hit protein's sequence so a peptide's
+/// modifications can be localized to real protein positions via
+/// [`localize_mods`], the same way [`load_mzid`] does.
+pub fn load_pepxml<P: AsRef<Path>>(path: P, fasta: &Fasta) -> io::Result<Grouped> {
+    let path = path.as_ref();
+    let file = fs::File::open(path)?;
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    reader.trim_text(true);
+
+    let mut mod_table = ModTable::new();
+    let mut table: HashMap<String, Protein> = HashMap::new();
+
+    let mut buf = Vec::new();
+    let mut cur_hit: Option<(String, String, PeptideDef)> = None;
+    let mut cur_scan: Option<ScanId> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(XmlEvent::Start(ref e)) | Ok(XmlEvent::Empty(ref e)) => match e.name().as_ref() {
+                b"spectrum_query" => {
+                    cur_scan = xml_attr(e, "start_scan").and_then(|s| s.parse().ok());
+                }
+                b"search_hit" => {
+                    let acc = xml_attr(e, "protein").unwrap_or_default();
+                    let seq = xml_attr(e, "peptide").unwrap_or_default();
+                    let desc = xml_attr(e, "protein_descr").unwrap_or_default();
+                    let _ = &desc;
+                    cur_hit = Some((
+                        acc,
+                        desc,
+                        PeptideDef {
+                            sequence: seq,
+                            mods: Vec::new(),
+                        },
+                    ));
+                }
+                b"mod_aminoacid_mass" => {
+                    if let Some((_, _, ref mut def)) = cur_hit {
+                        if let Some(loc) = xml_attr(e, "position").and_then(|s| s.parse().ok()) {
+                            let mass = xml_attr(e, "mass").and_then(|s| s.parse().ok()).unwrap_or(0.0);
+                            def.mods.push(ModSite {
+                                location: loc,
+                                mass,
+                                name: None,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(XmlEvent::End(ref e)) => {
+                if e.name().as_ref() == b"search_hit" {
+                    if let Some((acc, desc, def)) = cur_hit.take() {
+                        if !acc.is_empty() {
+                            let (seq, peptide_residue) = apply_mods(&def);
+                            let raw_mods: Vec<(usize, ModId)> = def
+                                .mods
+                                .iter()
+                                .map(|m| {
+                                    let name = m
+                                        .name
+                                        .clone()
+                                        .unwrap_or_else(|| format!("{:+}", m.mass));
+                                    (m.location.saturating_sub(1), mod_table.register(&name, m.mass))
+                                })
+                                .collect();
+                            let (residue, mods) = localize_or_fallback(
+                                fasta,
+                                &acc,
+                                &def.sequence,
+                                &raw_mods,
+                                peptide_residue,
+                            );
+                            let spectra = match cur_scan {
+                                Some(scan) => SpectrumIds::FileUnknown(vec![scan]),
+                                None => SpectrumIds::None,
+                            };
+                            table
+                                .entry(acc.clone())
+                                .or_insert_with(|| Protein::new(acc.clone(), desc))
+                                .add_ratio(residue, &seq, None, 1, spectra, None, &mods);
+                        }
+                    }
+                } else if e.name().as_ref() == b"spectrum_query" {
+                    cur_scan = None;
+                }
+            }
+            Ok(XmlEvent::Eof) => break,
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed pepXML at position {}: {}", reader.buffer_position(), e),
+                ))
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(Grouped {
+        proteins: table,
+        path: path.file_name().unwrap().to_str().unwrap().to_string(),
+        sequences: HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn write_mzid(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("test-{}.mzid", std::process::id()));
+        let mut f = fs::File::create(&path).unwrap();
+        f.write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    fn empty_fasta() -> Fasta {
+        Fasta {
+            map: HashMap::new(),
+            decoys: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn load_mzid_captures_score() {
+        let path = write_mzid(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MzIdentML>
+              <SequenceCollection>
+                <DBSequence id="DBSeq1" accession="P12345"/>
+                <Peptide id="Pep1">
+                  <PeptideSequence>MEHQLL</PeptideSequence>
+                </Peptide>
+                <PeptideEvidence id="PE1" peptide_ref="Pep1" dBSequence_ref="DBSeq1"/>
+              </SequenceCollection>
+              <DataCollection>
+                <AnalysisData>
+                  <SpectrumIdentificationList>
+                    <SpectrumIdentificationResult spectrumID="controllerType=0 controllerNumber=1 scan=42">
+                      <SpectrumIdentificationItem peptide_ref="Pep1">
+                        <cvParam accession="MS:1002257" name="Comet:xcorr" value="3.45"/>
+                        <PeptideEvidenceRef peptideEvidence_ref="PE1"/>
+                      </SpectrumIdentificationItem>
+                    </SpectrumIdentificationResult>
+                  </SpectrumIdentificationList>
+                </AnalysisData>
+              </DataCollection>
+            </MzIdentML>"#,
+        );
+
+        let grouped = load_mzid(&path, &empty_fasta()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let prot = grouped.proteins.get("P12345").unwrap();
+        let pep = prot.get(0, &[]).unwrap();
+        assert_eq!(pep.sequence, "MEHQLL");
+        assert_eq!(pep.score, Some(3.45));
+    }
+
+    #[test]
+    fn load_mzid_localizes_named_modification_against_protein() {
+        let path = write_mzid(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MzIdentML>
+              <SequenceCollection>
+                <DBSequence id="DBSeq1" accession="P12345"/>
+                <Peptide id="Pep1">
+                  <PeptideSequence>MEHQLL</PeptideSequence>
+                  <Modification location="3" monoisotopicMassDelta="57.02146">
+                    <cvParam accession="UNIMOD:4" name="Carbamidomethyl"/>
+                  </Modification>
+                </Peptide>
+                <PeptideEvidence id="PE1" peptide_ref="Pep1" dBSequence_ref="DBSeq1"/>
+              </SequenceCollection>
+              <DataCollection>
+                <AnalysisData>
+                  <SpectrumIdentificationList>
+                    <SpectrumIdentificationResult spectrumID="scan=7">
+                      <SpectrumIdentificationItem peptide_ref="Pep1">
+                        <PeptideEvidenceRef peptideEvidence_ref="PE1"/>
+                      </SpectrumIdentificationItem>
+                    </SpectrumIdentificationResult>
+                  </SpectrumIdentificationList>
+                </AnalysisData>
+              </DataCollection>
+            </MzIdentML>"#,
+        );
+
+        let mut fasta = empty_fasta();
+        fasta
+            .map
+            .insert("P12345".to_string(), "AAAMEHQLLAAA".to_string());
+
+        let grouped = load_mzid(&path, &fasta).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let prot = grouped.proteins.get("P12345").unwrap();
+        assert_eq!(prot.peptides.len(), 1);
+        let pep = &prot.peptides[0];
+        // "MEHQLL" sits at offset 3 in "AAAMEHQLLAAA"; the mod is on the
+        // peptide's 3rd residue (0-based offset 2), so its localized
+        // protein-wide position is 3 + 2 = 5. `residue` must agree with
+        // `mods` about this same protein-absolute coordinate system.
+        assert_eq!(pep.mods.len(), 1);
+        assert_eq!(pep.mods[0].0, 5);
+        assert_eq!(pep.residue, 5);
+    }
+
+    #[test]
+    fn single_mod_marks_residue() {
+        let def = PeptideDef {
+            sequence: String::from("MEHQLL"),
+            mods: vec![ModSite {
+                location: 3,
+                mass: 0.0,
+                name: None,
+            }],
+        };
+        let (seq, residue) = apply_mods(&def);
+        assert_eq!(seq, "ME*HQLL");
+        assert_eq!(residue, 3);
+    }
+
+    #[test]
+    fn no_mod_leaves_sequence_untouched() {
+        let def = PeptideDef {
+            sequence: String::from("MEHQLL"),
+            mods: Vec::new(),
+        };
+        let (seq, residue) = apply_mods(&def);
+        assert_eq!(seq, "MEHQLL");
+        assert_eq!(residue, 0);
+    }
+}
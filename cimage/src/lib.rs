@@ -67,20 +67,37 @@ use std::io::{self, BufRead, BufReader};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 
+use rayon::prelude::*;
+
 mod aggregate;
 mod aggregation;
+mod ambiguity;
+mod enrichment;
+mod enzyme;
 mod filter;
+mod ident;
+mod modification;
 mod parser;
 mod peptide;
 mod protein;
+mod sketch;
 mod stats;
 
 pub use aggregate::*;
 pub use aggregation::*;
-pub use filter::{Filter, PeptideFilter, ProteinFilter, RatioFilter, SecondPassFilter};
+pub use ambiguity::AmbiguityMode;
+pub use enrichment::{go_enrichment, keyword_enrichment, EnrichmentResult};
+pub use enzyme::Enzyme;
+pub use filter::{
+    Filter, FilterSpec, PeptideFilter, PeptideFilterSpec, ProteinFilter, RatioFilter,
+    SecondPassFilter,
+};
+pub use ident::{load_mzid, load_pepxml};
+pub use modification::{parse_proforma, ModId, ModTable};
 pub use parser::Raw;
-pub use peptide::Peptide;
+pub use peptide::{Peptide, ScanId, SpectrumIds};
 pub use protein::Protein;
+pub use sketch::{HyperLogLog, MinHash};
 
 pub type Residue = u16;
 
@@ -88,6 +105,11 @@ pub type Residue = u16;
 pub struct Grouped {
     pub proteins: HashMap<String, Protein>,
     pub path: String,
+    /// Parent protein sequences, keyed by accession, for peptides that
+    /// need their termini checked against the real protein (e.g.
+    /// [`PeptideFilter::HalfTrypticEnzyme`]). Empty unless populated by
+    /// [`Grouped::load_fasta`].
+    pub sequences: HashMap<String, String>,
 }
 
 pub struct Filtered {
@@ -96,11 +118,25 @@ pub struct Filtered {
 }
 
 impl Grouped {
+    /// Load parent protein sequences from a FASTA database, so that
+    /// peptide filters which need to check real protein termini (e.g.
+    /// [`PeptideFilter::HalfTrypticEnzyme`]) have something to check
+    /// against. Accessions not present in `self.proteins` are loaded too
+    /// and simply go unused.
+    pub fn load_fasta<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.sequences = uniprot::fasta::Fasta::open(path)?.map;
+        Ok(())
+    }
+
+    /// Filter every protein in parallel via rayon's global thread pool.
+    /// `Filter` only ever holds read-only references, so handing each
+    /// protein to `filters.filter` off the calling thread is safe - see
+    /// [`Filtered`]'s `unsafe impl Send`/`Sync`.
     pub fn filter<'a>(self, filters: &Filter<'a>) -> Filtered {
         let proteins = self
             .proteins
-            .into_iter()
-            .filter_map(|(acc, mut protein)| {
+            .into_par_iter()
+            .filter_map(|(acc, protein)| {
                 // protein.collapse_redundant_sites();
                 Some((acc, filters.filter(protein)?))
             })
@@ -111,23 +147,77 @@ impl Grouped {
             path: self.path,
         }
     }
+
+    /// Like [`Grouped::filter`], but runs on a scoped rayon thread pool
+    /// bounded to `threads` workers, so a caller embedding this in a
+    /// larger tool (e.g. one already running its own thread pool) can
+    /// control how much concurrency this step is allowed to use.
+    pub fn filter_with_threads<'a>(self, filters: &Filter<'a>, threads: usize) -> Filtered {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to build rayon thread pool");
+        pool.install(|| {
+            let proteins = self
+                .proteins
+                .into_iter()
+                .par_bridge()
+                .filter_map(|(acc, protein)| Some((acc, filters.filter(protein)?)))
+                .collect::<HashMap<String, Protein>>();
+
+            Filtered {
+                proteins,
+                path: self.path,
+            }
+        })
+    }
 }
 
+/// log2(4), the conventional engagement threshold for a ligandable site:
+/// a four-fold competed ratio between vehicle and compound treatment
+const ENGAGEMENT_THRESHOLD_LOG2: f64 = 2.0;
+
 impl Filtered {
     pub fn write<P: AsRef<std::path::Path>>(&self, p: P) -> std::io::Result<()> {
         use std::io::prelude::*;
-        let mut f = std::fs::File::create(p)?;
-        writeln!(f, "identifier\tms2\tratio")?;
+
+        // Collect rows first so the Benjamini-Hochberg correction can see
+        // every site's p-value before any line is written.
+        let mut rows = Vec::new();
+        let mut pvalues = Vec::new();
         for (acc, prot) in &self.proteins {
             for peptide in &prot.peptides {
                 if let Some(r) = peptide.median_ratio() {
-                    writeln!(f, "{}_{}\t{}\t{}", acc, peptide.residue, peptide.ms2, r)?;
+                    let log2_ratios: Vec<f64> = peptide
+                        .ratios
+                        .iter()
+                        .filter_map(|r| *r)
+                        .filter(|&r| r > 0.0)
+                        .map(|r| (r as f64).log2())
+                        .collect();
+                    let p = stats::one_sample_t_test(&log2_ratios, ENGAGEMENT_THRESHOLD_LOG2);
+                    pvalues.push(p.unwrap_or(1.0));
+                    rows.push((acc, peptide, r));
                 }
-                // for ratio in peptide.ratios.iter().copied().filter_map(|f| f) {
-                //     writeln!(f, "{}_{}\t{}\t{}", acc, peptide.residue, peptide.ms2, ratio)?;
-                // }
             }
         }
+
+        let qvalues = stats::benjamini_hochberg(&pvalues);
+
+        let mut f = std::fs::File::create(p)?;
+        writeln!(f, "identifier\tms2\tratio\tq_value\tprovenance")?;
+        for ((acc, peptide, r), q) in rows.into_iter().zip(qvalues) {
+            writeln!(
+                f,
+                "{}_{}\t{}\t{}\t{}\t{}",
+                acc,
+                peptide.residue,
+                peptide.ms2,
+                r,
+                q,
+                peptide.spectra.format_provenance()
+            )?;
+        }
         Ok(())
     }
 }
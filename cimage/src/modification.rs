@@ -0,0 +1,170 @@
+//! Generalized, named-modification model.
+//!
+//! The CIMAGE-native pipeline ([`crate::parser`], [`Peptide::residue`])
+//! hardcodes a single modification convention: one `*` marking one
+//! modified residue. This module adds a parallel, opt-in model for
+//! multiplexed/multi-probe experiments that carry several distinct,
+//! independently-localized modifications per peptide, parsed from
+//! ProForma-style bracket notation (`PEPT[+57.02]IDE`,
+//! `C[Carbamidomethyl]`).
+//!
+//! It doesn't replace the `*`/[`crate::Residue`] pipeline that the rest
+//! of the crate is built on - existing CIMAGE data has exactly one
+//! modification per peptide and doesn't need this - but gives callers
+//! ingesting richer search-engine output (see [`crate::ident`]) a way
+//! to carry and localize an arbitrary modification set.
+
+use std::collections::HashMap;
+use uniprot::Entry;
+
+/// Interned handle into a [`ModTable`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct ModId(u32);
+
+/// A name -> monoisotopic mass table, assigning each distinct
+/// modification a stable [`ModId`] the first time it's seen
+#[derive(Clone, Debug, Default)]
+pub struct ModTable {
+    by_name: HashMap<String, ModId>,
+    masses: Vec<f64>,
+    names: Vec<String>,
+}
+
+impl ModTable {
+    pub fn new() -> Self {
+        ModTable::default()
+    }
+
+    /// Look up (or register, if this is the first time it's been seen)
+    /// a named modification, returning its [`ModId`]
+    pub fn register(&mut self, name: &str, mass: f64) -> ModId {
+        if let Some(id) = self.by_name.get(name) {
+            return *id;
+        }
+        let id = ModId(self.names.len() as u32);
+        self.by_name.insert(name.to_string(), id);
+        self.names.push(name.to_string());
+        self.masses.push(mass);
+        id
+    }
+
+    pub fn mass(&self, id: ModId) -> f64 {
+        self.masses[id.0 as usize]
+    }
+
+    pub fn name(&self, id: ModId) -> &str {
+        &self.names[id.0 as usize]
+    }
+}
+
+/// Parse a ProForma-style modified sequence such as `PEPT[+57.02]IDE`
+/// or `C[Carbamidomethyl]PEPTIDE` into its stripped residue sequence
+/// and the localized `(residue_index, ModId)` pairs, registering any
+/// unseen modification names/masses into `table`.
+///
+/// A bracketed token starting with `+` or `-` is treated as a bare mass
+/// delta and keyed in the table under its own numeric-string name (e.g.
+/// `+57.02`); any other token is treated as a named modification with
+/// an unknown mass of `0.0` unless already registered.
+pub fn parse_proforma(seq: &str, table: &mut ModTable) -> (String, Vec<(usize, ModId)>) {
+    let mut sequence = String::with_capacity(seq.len());
+    let mut mods = Vec::new();
+
+    let mut chars = seq.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '[' {
+            let mut token = String::new();
+            for c2 in chars.by_ref() {
+                if c2 == ']' {
+                    break;
+                }
+                token.push(c2);
+            }
+            let residue_idx = sequence.len().saturating_sub(1);
+            let id = if token.starts_with('+') || token.starts_with('-') {
+                let mass = token.parse::<f64>().unwrap_or(0.0);
+                table.register(&token, mass)
+            } else {
+                // Named modification; mass is unknown from notation alone
+                // unless it's already been registered with one.
+                match table.by_name.get(&token) {
+                    Some(id) => *id,
+                    None => table.register(&token, 0.0),
+                }
+            };
+            mods.push((residue_idx, id));
+        } else {
+            sequence.push(c);
+        }
+    }
+
+    (sequence, mods)
+}
+
+/// Localize a ProForma-parsed modification set (`mods`, relative to
+/// `stripped_sequence`) against every position `entry` matches, shifting
+/// each mod's offset by the match start so it becomes absolute w.r.t.
+/// `entry.sequence` - the multi-modification analogue of
+/// [`uniprot::Entry::assign_residues`], which only localizes the single
+/// `*` marker.
+pub fn localize_mods(
+    entry: &Entry,
+    stripped_sequence: &str,
+    mods: &[(usize, ModId)],
+) -> Vec<Vec<(usize, ModId)>> {
+    entry
+        .assign_residues(stripped_sequence)
+        .into_iter()
+        .map(|start| {
+            mods.iter()
+                .map(|&(offset, id)| (start + offset, id))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_mass_delta_notation() {
+        let mut table = ModTable::new();
+        let (seq, mods) = parse_proforma("PEPT[+57.02]IDE", &mut table);
+        assert_eq!(seq, "PEPTIDE");
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].0, 3);
+        assert!((table.mass(mods[0].1) - 57.02).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parses_named_notation_and_leading_mod() {
+        let mut table = ModTable::new();
+        let (seq, mods) = parse_proforma("C[Carbamidomethyl]PEPTIDE", &mut table);
+        assert_eq!(seq, "CPEPTIDE");
+        assert_eq!(mods.len(), 1);
+        assert_eq!(mods[0].0, 0);
+        assert_eq!(table.name(mods[0].1), "Carbamidomethyl");
+    }
+
+    #[test]
+    fn reuses_mod_id_for_repeated_modification() {
+        let mut table = ModTable::new();
+        let (_, mods) = parse_proforma("C[Carbamidomethyl]PEPC[Carbamidomethyl]TIDE", &mut table);
+        assert_eq!(mods.len(), 2);
+        assert_eq!(mods[0].1, mods[1].1);
+    }
+
+    #[test]
+    fn localizes_mods_against_every_match() {
+        let mut table = ModTable::new();
+        let id = table.register("Carbamidomethyl", 57.02);
+        let entry = Entry {
+            accession: String::from("Q1"),
+            identifier: String::from("TEST"),
+            sequence: String::from("AACPEPTIDEAACPEPTIDE"),
+        };
+        let localized = localize_mods(&entry, "CPEPTIDE", &[(0, id)]);
+        assert_eq!(localized, vec![vec![(2, id)], vec![(12, id)]]);
+    }
+}
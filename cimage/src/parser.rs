@@ -49,9 +49,27 @@ impl<'a> Iterator for Pitchfork<'a> {
 pub struct Raw {
     pub events: Vec<Event>,
     pub ms2: HashMap<String, usize>,
+    /// MS2 scan numbers backing each peptide sequence, each paired with
+    /// the raw file it came from, parsed from the DTASelect FileName field
+    /// (see [`parse_scan_field`]). [`Raw::group`] groups them by file to
+    /// populate [`SpectrumIds`].
+    pub scans: HashMap<String, Vec<(PathBuf, ScanId)>>,
     pub path: PathBuf,
 }
 
+/// Parse a DTASelect FileName field of the form `<raw_file>.<low_scan>.<high_scan>.<charge>`
+/// into the originating raw file and its (low) scan number.
+fn parse_scan_field(field: &str) -> Option<(PathBuf, ScanId)> {
+    let parts: Vec<&str> = field.split('.').collect();
+    if parts.len() < 4 {
+        return None;
+    }
+    let n = parts.len();
+    let scan: ScanId = parts[n - 3].parse().ok()?;
+    let file = parts[..n - 3].join(".");
+    Some((PathBuf::from(file), scan))
+}
+
 /// Quantified MS1 event
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct Event {
@@ -142,6 +160,7 @@ impl Raw {
     /// and ratiometric information for peptide events
     pub fn load<P: AsRef<Path>>(output_rt: P, dtaselect: P, fasta: &Fasta) -> io::Result<Raw> {
         let mut ms2 = HashMap::new();
+        let mut scans: HashMap<String, Vec<(PathBuf, ScanId)>> = HashMap::new();
         let mut buffer = Vec::new();
         let mut file = fs::File::open(output_rt.as_ref())?;
         file.read_to_end(&mut buffer)?;
@@ -166,6 +185,9 @@ impl Raw {
                     let mut seq: String = fields[14].into();
                     seq = seq.replace("(464.24957)", "*");
                     seq = seq.replace("(470.26338)", "*");
+                    if let Some((file, scan)) = parse_scan_field(fields[1]) {
+                        scans.entry(seq.clone()).or_insert_with(Vec::new).push((file, scan));
+                    }
                     *ms2.entry(seq.into()).or_insert(0) += 1;
                 }
             }
@@ -174,6 +196,7 @@ impl Raw {
         Ok(Raw {
             events,
             ms2,
+            scans,
             path: PathBuf::from(output_rt.as_ref()),
         })
     }
@@ -184,23 +207,35 @@ impl Raw {
         let mut used: HashSet<(String, String)> = HashSet::new();
 
         for ev in self.events {
-            // only add ms2 once.
-            let ms2 = if !used.contains(&(ev.acc.clone(), ev.seq.clone())) {
+            // only add ms2/spectra once per (accession, sequence).
+            let (ms2, spectra) = if !used.contains(&(ev.acc.clone(), ev.seq.clone())) {
                 used.insert((ev.acc.clone(), ev.seq.clone()));
-                self.ms2.get(&ev.seq).copied().unwrap_or(0)
+                let ms2 = self.ms2.get(&ev.seq).copied().unwrap_or(0);
+                let spectra = match self.scans.get(&ev.seq) {
+                    Some(scans) => {
+                        let mut by_file: HashMap<PathBuf, Vec<ScanId>> = HashMap::new();
+                        for (file, scan) in scans {
+                            by_file.entry(file.clone()).or_insert_with(Vec::new).push(*scan);
+                        }
+                        SpectrumIds::FileKnown(by_file.into_iter().collect())
+                    }
+                    None => SpectrumIds::None,
+                };
+                (ms2, spectra)
             } else {
-                0
+                (0, SpectrumIds::None)
             };
 
             table
                 .entry(ev.acc.clone())
                 .or_insert_with(|| Protein::new(ev.acc.clone(), ev.desc.clone()))
-                .add_ratio(ev.residue, &ev.seq, Some(ev.ratio), ms2);
+                .add_ratio(ev.residue, &ev.seq, Some(ev.ratio), ms2, spectra, None, &[]);
         }
 
         Grouped {
             proteins: table,
             path: self.path.file_name().unwrap().to_str().unwrap().to_string(),
+            sequences: HashMap::new(),
         }
     }
 }
@@ -238,7 +273,7 @@ mod test {
 
         macro_rules! pep {
             ($seq: expr, $site:expr, $($r:expr),+) => {
-                Peptide { sequence: $seq.into(), ms2: 0, residue: $site, ratios: vec![$(Some($r)),+]}
+                Peptide { sequence: $seq.into(), ms2: 0, residue: $site, ratios: vec![$(Some($r)),+], ..Peptide::default() }
             };
         }
 
@@ -254,6 +289,7 @@ mod test {
         let raw = Raw {
             events,
             ms2: HashMap::default(),
+            scans: HashMap::default(),
             path: PathBuf::from("test.txt"),
         };
 
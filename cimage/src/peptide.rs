@@ -9,6 +9,101 @@ pub struct Peptide {
     /// A ratio can be set to None if it's filtered out, or if it's
     /// not quantified.
     pub ratios: Vec<Option<f32>>,
+    /// Localized modification sites beyond the single `*`/`residue`
+    /// convention, for peptides parsed from a ProForma-style
+    /// multi-modification notation (see [`crate::modification`]). Empty
+    /// for the CIMAGE-native single-probe pipeline.
+    pub mods: Vec<(usize, super::ModId)>,
+    /// Best (highest) per-spectrum identification score backing this
+    /// site, e.g. an mzIdentML `SpectrumIdentificationItem`'s search
+    /// engine score. `None` for CIMAGE-native ratio data, which carries
+    /// no identification score of its own.
+    pub score: Option<f32>,
+    /// Which raw file(s) and MS2 scan(s) this peptide's surviving ratios
+    /// trace back to, so a value that survives filtering can still be
+    /// linked back to its spectra. See [`SpectrumIds`].
+    pub spectra: SpectrumIds,
+}
+
+/// An MS2 scan number
+pub type ScanId = u32;
+
+/// Where a peptide's supporting spectra came from. Mirrors rustyms'
+/// unified scan-extraction design, which exposes file-known vs
+/// file-unknown spectrum references for an identified peptide rather
+/// than assuming a single well-known source file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SpectrumIds {
+    #[default]
+    None,
+    /// Scan numbers with no known originating raw file
+    FileUnknown(Vec<ScanId>),
+    /// Scan numbers grouped by the raw file they came from
+    FileKnown(Vec<(PathBuf, Vec<ScanId>)>),
+}
+
+impl SpectrumIds {
+    /// Combine another peptide's spectrum provenance into this one, e.g.
+    /// when the same site is re-observed across multiple search engine
+    /// hits or raw files.
+    pub fn merge(&mut self, other: SpectrumIds) {
+        *self = match (std::mem::take(self), other) {
+            (SpectrumIds::None, other) => other,
+            (this, SpectrumIds::None) => this,
+            (SpectrumIds::FileUnknown(mut a), SpectrumIds::FileUnknown(b)) => {
+                a.extend(b);
+                SpectrumIds::FileUnknown(a)
+            }
+            (SpectrumIds::FileKnown(mut a), SpectrumIds::FileKnown(b)) => {
+                for (file, scans) in b {
+                    match a.iter_mut().find(|(f, _)| *f == file) {
+                        Some((_, existing)) => existing.extend(scans),
+                        None => a.push((file, scans)),
+                    }
+                }
+                SpectrumIds::FileKnown(a)
+            }
+            // Mix of known and unknown provenance: keep the known half
+            // and fold the unknown scans in under an empty file path
+            // rather than discarding them.
+            (SpectrumIds::FileUnknown(unk), SpectrumIds::FileKnown(mut known))
+            | (SpectrumIds::FileKnown(mut known), SpectrumIds::FileUnknown(unk)) => {
+                known.push((PathBuf::new(), unk));
+                SpectrumIds::FileKnown(known)
+            }
+        };
+    }
+
+    /// Format as `file:scan;scan|file:scan`, one `|`-delimited group per
+    /// known file, and `;`-delimited scan numbers within a group.
+    /// [`SpectrumIds::FileUnknown`] is written as bare `scan;scan`; scans
+    /// with unknown file merged into a [`SpectrumIds::FileKnown`] appear
+    /// under an empty file name.
+    pub fn format_provenance(&self) -> String {
+        match self {
+            SpectrumIds::None => String::new(),
+            SpectrumIds::FileUnknown(scans) => scans
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            SpectrumIds::FileKnown(files) => files
+                .iter()
+                .map(|(file, scans)| {
+                    format!(
+                        "{}:{}",
+                        file.display(),
+                        scans
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect::<Vec<_>>()
+                            .join(";")
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("|"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Hash)]
@@ -120,6 +215,39 @@ impl Peptide {
         front && end
     }
 
+    /// Locate this peptide within `protein_seq` and classify its termini
+    /// against `enzyme`'s cleavage rules, rather than guessing from the
+    /// `X.peptide.Y` flanking residues embedded in [`Peptide::sequence`]
+    /// (see [`Peptide::is_not_half_tryptic`]). A terminus is enzyme-
+    /// consistent if it sits at the protein's N/C-terminus, or if
+    /// `enzyme` would cleave between the peptide and its neighboring
+    /// residue there. Returns `false` (the same as for a half-tryptic
+    /// peptide) if the peptide can't be located in `protein_seq`.
+    pub fn is_not_half_tryptic_against(&self, protein_seq: &str, enzyme: &crate::Enzyme) -> bool {
+        let bare = self
+            .sequence
+            .split('.')
+            .nth(1)
+            .unwrap_or(&self.sequence)
+            .chars()
+            .filter(|&c| c != '*')
+            .collect::<String>();
+
+        let start = match protein_seq.find(&bare) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let end = start + bare.len();
+
+        let protein_chars = protein_seq.as_bytes();
+        let n_term_ok = start == 0
+            || enzyme.cleaves(protein_chars[start - 1] as char, protein_chars[start] as char);
+        let c_term_ok = end == protein_chars.len()
+            || enzyme.cleaves(protein_chars[end - 1] as char, protein_chars[end] as char);
+
+        n_term_ok && c_term_ok
+    }
+
     /// Remove ratios equal to 20 if the coefficient of variation for non-zero
     /// ratios is greater than or equal to `cutoff`
     pub fn cv_filter(&mut self, cutoff: f32) {
@@ -187,6 +315,7 @@ mod test {
             sequence: String::new(),
             residue: 0,
             ms2: 0,
+            ..Peptide::default()
         }
     }
 
@@ -196,6 +325,7 @@ mod test {
             residue: 0,
             ms2: 0,
             sequence: String::from(s),
+            ..Peptide::default()
         }
     }
 
@@ -206,6 +336,26 @@ mod test {
         assert_eq!(peptide.non_zeroes(), vec![1.2, 1.3])
     }
 
+    #[test]
+    fn is_not_half_tryptic_against_checks_real_termini() {
+        let enzyme = Enzyme::trypsin();
+        let protein_seq = "MRGLAITFVSDENDAKAFOO";
+
+        // "GLAITFVSDENDAK" sits right after an R and right before an A,
+        // both enzyme-consistent cuts for trypsin
+        let fully_tryptic = sequence("R.GLAITFVSDENDAK.A");
+        assert!(fully_tryptic.is_not_half_tryptic_against(protein_seq, &enzyme));
+
+        // "LAITFVSDENDAK" starts mid-protein right after a G, which
+        // trypsin would never cut after - N-terminus isn't consistent
+        let half_tryptic = sequence("G.LAITFVSDENDAK.A");
+        assert!(!half_tryptic.is_not_half_tryptic_against(protein_seq, &enzyme));
+
+        // Not present in the protein sequence at all
+        let missing = sequence("K.ZZZZZZZZZZ.R");
+        assert!(!missing.is_not_half_tryptic_against(protein_seq, &enzyme));
+    }
+
     #[test]
     fn tryptic_ends() {
         assert_eq!(sequence("R.FGTKGLAITFVSDENDAK.I").tryptic_ends(), 2);
@@ -270,4 +420,29 @@ mod test {
         assert_eq!(x.unwrap(), 10.0);
         assert_eq!(x.unwrap(), 10.0);
     }
+
+    #[test]
+    fn spectrum_ids_merge_combines_same_file() {
+        let mut a = SpectrumIds::FileKnown(vec![(PathBuf::from("a.raw"), vec![1, 2])]);
+        let b = SpectrumIds::FileKnown(vec![(PathBuf::from("a.raw"), vec![3])]);
+        a.merge(b);
+        assert_eq!(
+            a,
+            SpectrumIds::FileKnown(vec![(PathBuf::from("a.raw"), vec![1, 2, 3])])
+        );
+    }
+
+    #[test]
+    fn spectrum_ids_format_provenance() {
+        assert_eq!(SpectrumIds::None.format_provenance(), "");
+        assert_eq!(
+            SpectrumIds::FileUnknown(vec![10, 20]).format_provenance(),
+            "10;20"
+        );
+        let known = SpectrumIds::FileKnown(vec![
+            (PathBuf::from("a.raw"), vec![1, 2]),
+            (PathBuf::from("b.raw"), vec![3]),
+        ]);
+        assert_eq!(known.format_provenance(), "a.raw:1;2|b.raw:3");
+    }
 }
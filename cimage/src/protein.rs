@@ -1,5 +1,13 @@
 use super::*;
 
+/// A [`Protein`] site key: the single-probe `residue`, plus any
+/// additional localized modifications beyond it (see
+/// [`crate::modification`]). Keying on the full set rather than just
+/// `residue` lets multiplexed/multi-probe experiments tell apart two
+/// peptides that share a probe site but carry different co-occurring
+/// modifications.
+pub type SiteKey = (Residue, Vec<(usize, ModId)>);
+
 #[derive(Clone, Debug, Default, PartialEq)]
 /// Container for [`Peptide`] sequences and their quantified ratios
 ///
@@ -13,7 +21,7 @@ pub struct Protein {
     ///
     /// Should look into average length of peptide Vec to determine
     /// if the extra memory usage here is worth the speedup.
-    pub map: HashMap<Residue, usize>,
+    pub map: HashMap<SiteKey, usize>,
 }
 
 impl Protein {
@@ -36,14 +44,30 @@ impl Protein {
 
     /// Add a ratio for a given peptide sequence to the [`Protein`]
     ///
-    /// If the protein already contains a peptide with the same sequence as
-    /// the peptide being added, the new ratio will be appended to
-    /// the existing peptide match's ratios.
-    pub fn add_ratio(&mut self, residue: Residue, seq: &str, ratio: Option<f32>, ms2: usize) {
-        match self.map.get(&residue) {
+    /// If the protein already contains a peptide with the same `residue`
+    /// and `mods` as the peptide being added, the new ratio will be
+    /// appended to the existing peptide match's ratios. `score`, if given,
+    /// is kept as the highest identification score seen for this site
+    /// across every call (e.g. re-observing the same site from a second
+    /// spectrum with a better search engine score). `mods` is empty for
+    /// the CIMAGE-native single-probe pipeline.
+    pub fn add_ratio(
+        &mut self,
+        residue: Residue,
+        seq: &str,
+        ratio: Option<f32>,
+        ms2: usize,
+        spectra: SpectrumIds,
+        score: Option<f32>,
+        mods: &[(usize, ModId)],
+    ) {
+        let key: SiteKey = (residue, mods.to_vec());
+        match self.map.get(&key) {
             Some(idx) => {
                 self.peptides[*idx].ratios.push(ratio);
                 self.peptides[*idx].ms2 += ms2;
+                self.peptides[*idx].spectra.merge(spectra);
+                self.peptides[*idx].score = Self::best_score(self.peptides[*idx].score, score);
 
                 // if self.peptides[*idx].ms2 == 0 {
                 //     dbg!(&self.peptides[*idx]);
@@ -56,12 +80,24 @@ impl Protein {
                     residue: residue,
                     ms2,
                     ratios: vec![ratio],
+                    spectra,
+                    score,
+                    mods: mods.to_vec(),
+                    ..Peptide::default()
                 });
-                self.map.insert(residue, idx);
+                self.map.insert(key, idx);
             }
         }
     }
 
+    fn best_score(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
     pub fn collapse_redundant_sites(&mut self) {
         let mut count: HashMap<String, Vec<_>> = HashMap::new();
         for (idx, p) in self.peptides.iter().enumerate() {
@@ -71,6 +107,26 @@ impl Protein {
                 .push((idx, p.residue));
         }
 
+        self.collapse_indices(count);
+    }
+
+    /// Like [`Protein::collapse_redundant_sites`], but two peptide
+    /// sequences are considered the same site if they're only equal
+    /// after normalizing mass-ambiguous residues under `mode` (see
+    /// [`crate::ambiguity`]) - e.g. an `I` in one search result and an
+    /// `L` at the same position in another are treated as the same
+    /// underlying peptide rather than distinct sites.
+    pub fn collapse_redundant_sites_ambiguous(&mut self, mode: AmbiguityMode) {
+        let mut count: HashMap<String, Vec<_>> = HashMap::new();
+        for (idx, p) in self.peptides.iter().enumerate() {
+            let normalized = ambiguity::normalize(&p.sequence.replace('*', ""), mode);
+            count.entry(normalized).or_insert_with(Vec::new).push((idx, p.residue));
+        }
+
+        self.collapse_indices(count);
+    }
+
+    fn collapse_indices(&mut self, count: HashMap<String, Vec<(usize, Residue)>>) {
         for (_, mut indices) in count {
             indices.sort_by(|a, b| a.1.cmp(&b.1));
             if indices.len() > 1 {
@@ -93,35 +149,41 @@ impl Protein {
             .peptides
             .iter()
             .enumerate()
-            .map(|(idx, pep)| (pep.residue, idx))
+            .map(|(idx, pep)| ((pep.residue, pep.mods.clone()), idx))
             .collect();
     }
 
     /// Add a new [`Peptide`] struct to the [`Protein`]
     ///
-    /// If the protein already contains a peptide with the same sequence as
-    /// the peptide being added, the new peptide's ratios will be appended to
-    /// the existing peptide match's ratios.
+    /// If the protein already contains a peptide with the same `residue`
+    /// and `mods` as the peptide being added, the new peptide's ratios
+    /// will be appended to the existing peptide match's ratios.
     pub fn add_peptide(&mut self, peptide: Peptide) {
-        match self.map.get(&peptide.residue) {
+        let key: SiteKey = (peptide.residue, peptide.mods.clone());
+        match self.map.get(&key) {
             Some(idx) => {
                 self.peptides[*idx].ratios.extend(peptide.ratios);
                 self.peptides[*idx].ms2 += peptide.ms2;
+                self.peptides[*idx].spectra.merge(peptide.spectra);
             }
             None => {
                 let idx = self.peptides.len();
-                self.map.insert(peptide.residue, idx);
+                self.map.insert(key, idx);
                 self.peptides.push(peptide);
             }
         }
     }
 
-    pub fn get(&self, residue: Residue) -> Option<&Peptide> {
-        self.peptides.get(*self.map.get(&residue)?)
+    /// Look up a peptide by its full site key: `residue` plus any
+    /// localized modifications beyond it. Pass an empty `mods` slice for
+    /// the CIMAGE-native single-probe convention.
+    pub fn get(&self, residue: Residue, mods: &[(usize, ModId)]) -> Option<&Peptide> {
+        self.peptides.get(*self.map.get(&(residue, mods.to_vec()))?)
     }
 
-    pub fn get_mut(&mut self, residue: Residue) -> Option<&mut Peptide> {
-        self.peptides.get_mut(*self.map.get(&residue)?)
+    pub fn get_mut(&mut self, residue: Residue, mods: &[(usize, ModId)]) -> Option<&mut Peptide> {
+        self.peptides
+            .get_mut(*self.map.get(&(residue, mods.to_vec()))?)
     }
 
     /// Similar to a [`HashMap`]'s `Entry`, return mutable reference to
@@ -155,10 +217,27 @@ impl Protein {
             residue: 0,
             ms2: 0,
             ratios: medians,
+            ..Peptide::default()
         };
         mock.median_ratio()
     }
 
+    /// Group this protein's peptides by their full localized modification
+    /// set (see [`crate::modification`]) rather than the single `residue`
+    /// site the rest of the crate indexes by. Peptides carrying no
+    /// [`Peptide::mods`] (the default, single-probe CIMAGE convention)
+    /// all fall under the empty-`Vec` key.
+    pub fn group_by_mods(&self) -> HashMap<Vec<(usize, ModId)>, Vec<&Peptide>> {
+        let mut grouped: HashMap<Vec<(usize, ModId)>, Vec<&Peptide>> = HashMap::new();
+        for peptide in &self.peptides {
+            grouped
+                .entry(peptide.mods.clone())
+                .or_insert_with(Vec::new)
+                .push(peptide);
+        }
+        grouped
+    }
+
     pub fn spectral_counts(&self) -> usize {
         self.peptides.iter().fold(0, |acc, x| {
             acc + x.ratios.iter().filter(|r| r.is_some()).count()
@@ -184,7 +263,7 @@ mod test {
         let mut prot = Protein::default();
         macro_rules! add {
             ($s:expr, $site:expr, $f:expr) => {
-                prot.add_ratio($site, $s.into(), Some($f), 0)
+                prot.add_ratio($site, $s.into(), Some($f), 0, SpectrumIds::None, None, &[])
             };
         }
         add!("MRL", 0, 1.);
@@ -193,10 +272,10 @@ mod test {
         add!("MEHQLL", 1, 20.0);
         assert_eq!(prot.peptides.len(), 2);
         assert_eq!(
-            prot.get(0).unwrap().ratios,
+            prot.get(0, &[]).unwrap().ratios,
             vec![Some(1.), Some(2.), Some(3.)]
         );
-        assert_eq!(prot.get(1).unwrap().ratios, vec![Some(20.0)]);
+        assert_eq!(prot.get(1, &[]).unwrap().ratios, vec![Some(20.0)]);
         assert_eq!(prot.spectral_counts(), 4);
     }
 
@@ -205,7 +284,7 @@ mod test {
         let mut prot = Protein::default();
         macro_rules! add {
             ($s:expr, $site:expr, $f:expr) => {
-                prot.add_ratio($site, $s.into(), Some($f), 0)
+                prot.add_ratio($site, $s.into(), Some($f), 0, SpectrumIds::None, None, &[])
             };
         }
         add!("K.LQFGSQPQVYNDFLDIMKEFK*SQSIDTPGVISR.V", 155, 1.);
@@ -215,14 +294,55 @@ mod test {
         add!("R.LK*VEDALSYLDQVK.L", 122, 20.0);
 
         assert_eq!(prot.peptides.len(), 3);
-        assert_eq!(prot.get(152).unwrap().ratios, vec![Some(2.), Some(3.5)]);
+        assert_eq!(prot.get(152, &[]).unwrap().ratios, vec![Some(2.), Some(3.5)]);
 
         prot.collapse_redundant_sites();
         assert_eq!(prot.peptides.len(), 2);
         assert_eq!(
-            prot.get(152).unwrap().ratios,
+            prot.get(152, &[]).unwrap().ratios,
             vec![Some(2.), Some(3.5), Some(1.), Some(5.)]
         );
-        assert_eq!(prot.get(155), None);
+        assert_eq!(prot.get(155, &[]), None);
+    }
+
+    #[test]
+    fn add_ratio_keeps_best_score() {
+        let mut prot = Protein::default();
+        prot.add_ratio(0, "MRL", Some(1.), 0, SpectrumIds::None, Some(0.5), &[]);
+        assert_eq!(prot.get(0, &[]).unwrap().score, Some(0.5));
+
+        prot.add_ratio(0, "MRL", Some(2.), 0, SpectrumIds::None, Some(0.9), &[]);
+        assert_eq!(prot.get(0, &[]).unwrap().score, Some(0.9));
+
+        prot.add_ratio(0, "MRL", Some(3.), 0, SpectrumIds::None, None, &[]);
+        assert_eq!(prot.get(0, &[]).unwrap().score, Some(0.9));
+    }
+
+    #[test]
+    fn collapse_redundant_sites_ambiguous_merges_i_l_variants() {
+        macro_rules! add {
+            ($prot:expr, $s:expr, $site:expr, $f:expr) => {
+                $prot.add_ratio($site, $s.into(), Some($f), 0, SpectrumIds::None, None, &[])
+            };
+        }
+
+        let mut prot = Protein::default();
+        add!(prot, "K.LQFGSQPQVYNDFLDIMKEFK*SQSIDTPGVISR.V", 155, 1.);
+        add!(prot, "K.IQFGSQPQVYNDFLDIMKEFK*SQSIDTPGVISR.V", 152, 5.);
+
+        assert_eq!(prot.peptides.len(), 2);
+
+        // Exact collapsing leaves both sites, since the sequences differ
+        // at the leading I/L
+        prot.collapse_redundant_sites();
+        assert_eq!(prot.peptides.len(), 2);
+
+        let mut prot = Protein::default();
+        add!(prot, "K.LQFGSQPQVYNDFLDIMKEFK*SQSIDTPGVISR.V", 155, 1.);
+        add!(prot, "K.IQFGSQPQVYNDFLDIMKEFK*SQSIDTPGVISR.V", 152, 5.);
+
+        prot.collapse_redundant_sites_ambiguous(AmbiguityMode::IsoleucineLeucine);
+        assert_eq!(prot.peptides.len(), 1);
+        assert_eq!(prot.get(155, &[]).unwrap().ratios, vec![Some(1.), Some(5.)]);
     }
 }
@@ -0,0 +1,207 @@
+//! Constant-memory cardinality and similarity estimation over large
+//! [`crate::Aggregate`] datasets.
+//!
+//! [`HyperLogLog`] gives a cheap estimate of the number of distinct
+//! quantified sites across arbitrarily many merged experiments, and
+//! [`MinHash`] gives a cheap estimate of how much two proteins' peptide
+//! sets overlap, without materializing and comparing the full sets.
+//! Both are purely additive - they don't change the existing exact
+//! [`crate::Protein::spectral_counts`] behavior.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A HyperLogLog cardinality estimator.
+///
+/// Hashes each inserted item, uses its low `p` bits to pick one of
+/// `2^p` registers, and keeps the maximum run of leading zeros seen in
+/// the remaining bits for that register. The harmonic mean of
+/// `2^register` across all registers, times the register count and a
+/// bias-correction constant, estimates the number of distinct items inserted using
+/// `O(2^p)` memory regardless of how many items are inserted.
+#[derive(Clone, Debug)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    p: u32,
+}
+
+impl HyperLogLog {
+    /// `p` controls accuracy vs. memory: `2^p` single-byte registers are
+    /// allocated, giving a relative error of roughly `1.04 / sqrt(2^p)`.
+    pub fn new(p: u32) -> Self {
+        let m = 1usize << p;
+        HyperLogLog {
+            registers: vec![0; m],
+            p,
+        }
+    }
+
+    /// Register a single observation
+    pub fn insert<T: Hash>(&mut self, item: &T) {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let m = self.registers.len() as u64;
+        let idx = (hash & (m - 1)) as usize;
+        let rest = hash >> self.p;
+        // Leading zeros within the remaining (64 - p) bits, plus one,
+        // per the standard HLL rank definition.
+        let rank = (rest.leading_zeros() - self.p).min(64 - self.p) as u8 + 1;
+
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+        }
+    }
+
+    /// Estimate the number of distinct items inserted so far
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum_inv: f64 = self
+            .registers
+            .iter()
+            .map(|&r| 2f64.powi(-(r as i32)))
+            .sum();
+        let raw = alpha * m * m / sum_inv;
+
+        let zero_registers = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zero_registers > 0 {
+            // Small-range correction: linear counting
+            m * (m / zero_registers as f64).ln()
+        } else if raw <= (1u64 << 32) as f64 / 30.0 {
+            raw
+        } else {
+            // Large-range correction for 32-bit-hash-style overflow;
+            // harmless here since we use a 64-bit hash, kept for parity
+            // with the canonical HLL estimator.
+            -((1u64 << 32) as f64) * (1.0 - raw / (1u64 << 32) as f64).ln()
+        }
+    }
+
+    /// Merge another sketch's registers into this one (union of the two
+    /// observed sets). Both sketches must have been built with the same `p`.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (a, b) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *b > *a {
+                *a = *b;
+            }
+        }
+    }
+}
+
+/// A bottom-k MinHash sketch: the `k` smallest hash values seen across
+/// all inserted items, used to estimate Jaccard similarity between two
+/// sets without holding either set in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MinHash {
+    k: usize,
+    hashes: Vec<u64>,
+}
+
+impl MinHash {
+    pub fn new(k: usize) -> Self {
+        MinHash {
+            k,
+            hashes: Vec::with_capacity(k),
+        }
+    }
+
+    pub fn insert<T: Hash>(&mut self, item: &T) {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if let Err(pos) = self.hashes.binary_search(&hash) {
+            self.hashes.insert(pos, hash);
+            self.hashes.truncate(self.k);
+        }
+    }
+
+    pub fn from_iter<T: Hash, I: IntoIterator<Item = T>>(k: usize, items: I) -> Self {
+        let mut sketch = MinHash::new(k);
+        for item in items {
+            sketch.insert(&item);
+        }
+        sketch
+    }
+
+    /// Estimate the Jaccard similarity between the sets backing `self`
+    /// and `other`: take the `k` smallest hashes across the union of
+    /// both sketches, and report what fraction of those are present in
+    /// both.
+    pub fn jaccard(&self, other: &MinHash) -> f64 {
+        let k = self.k.min(other.k);
+        if k == 0 {
+            return 0.0;
+        }
+
+        let mut merged = self.hashes.clone();
+        merged.extend(other.hashes.iter().copied());
+        merged.sort_unstable();
+        merged.dedup();
+        merged.truncate(k);
+
+        if merged.is_empty() {
+            return 0.0;
+        }
+
+        let shared = merged
+            .iter()
+            .filter(|h| self.hashes.contains(h) && other.hashes.contains(h))
+            .count();
+
+        shared as f64 / merged.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hyperloglog_estimates_within_tolerance() {
+        let mut hll = HyperLogLog::new(10);
+        for i in 0..10_000 {
+            hll.insert(&i);
+        }
+        let estimate = hll.estimate();
+        let error = (estimate - 10_000.0).abs() / 10_000.0;
+        assert!(error < 0.1, "relative error too high: {}", error);
+    }
+
+    #[test]
+    fn hyperloglog_merge_is_union() {
+        let mut a = HyperLogLog::new(10);
+        let mut b = HyperLogLog::new(10);
+        for i in 0..500 {
+            a.insert(&i);
+        }
+        for i in 250..750 {
+            b.insert(&i);
+        }
+        a.merge(&b);
+        let error = (a.estimate() - 750.0).abs() / 750.0;
+        assert!(error < 0.15, "relative error too high: {}", error);
+    }
+
+    #[test]
+    fn minhash_identical_sets_have_jaccard_one() {
+        let a = MinHash::from_iter(16, (0..100).map(|i| format!("PEP{}", i)));
+        let b = MinHash::from_iter(16, (0..100).map(|i| format!("PEP{}", i)));
+        assert!((a.jaccard(&b) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn minhash_disjoint_sets_have_jaccard_zero() {
+        let a = MinHash::from_iter(16, (0..100).map(|i| format!("A{}", i)));
+        let b = MinHash::from_iter(16, (0..100).map(|i| format!("B{}", i)));
+        assert_eq!(a.jaccard(&b), 0.0);
+    }
+}
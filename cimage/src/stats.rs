@@ -19,3 +19,235 @@ pub fn coefficient_variation(slice: &[f32]) -> f32 {
 
     (slice.iter().fold(0.0f32, |acc, x| acc + (x - mean).powi(2)) / n).sqrt() / mean
 }
+
+/// Lanczos approximation of the natural log of the gamma function,
+/// used to compute binomial coefficients for large N without overflow
+fn log_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.99999999999980993,
+        676.5203681218851,
+        -1259.1392167224028,
+        771.32342877765313,
+        -176.61502916214059,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.9843695780195716e-6,
+        1.5056327351493116e-7,
+    ];
+
+    if x < 0.5 {
+        // Reflection formula
+        (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - log_gamma(1.0 - x)
+    } else {
+        let x = x - 1.0;
+        let mut a = COEFFICIENTS[0];
+        let t = x + G + 0.5;
+        for (i, c) in COEFFICIENTS.iter().enumerate().skip(1) {
+            a += c / (x + i as f64);
+        }
+        0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+    }
+}
+
+/// log(n choose k), computed via [`log_gamma`] so it doesn't overflow
+/// for the large N seen in GO-term/keyword enrichment
+fn log_choose(n: usize, k: usize) -> f64 {
+    if k > n {
+        return f64::NEG_INFINITY;
+    }
+    log_gamma(n as f64 + 1.0) - log_gamma(k as f64 + 1.0) - log_gamma((n - k) as f64 + 1.0)
+}
+
+/// Upper-tail hypergeometric p-value: given a background of `n` items
+/// containing `big_k` successes, what is the probability of drawing at
+/// least `k` successes in a sample of size `sample` (without
+/// replacement)?
+///
+/// Used for GO-term/keyword over-representation and any other
+/// "is this subset enriched" test over a finite background.
+pub fn hypergeometric_sf(k: usize, big_k: usize, n: usize, sample: usize) -> f64 {
+    if n == 0 || sample == 0 {
+        return 1.0;
+    }
+    let upper = sample.min(big_k);
+    if k > upper {
+        return 0.0;
+    }
+    let log_total = log_choose(n, sample);
+    (k..=upper)
+        .map(|i| (log_choose(big_k, i) + log_choose(n - big_k, sample - i) - log_total).exp())
+        .sum::<f64>()
+        .min(1.0)
+}
+
+/// Regularized incomplete beta function `I_x(a, b)`, evaluated via the
+/// continued-fraction expansion from Numerical Recipes. Used to derive
+/// Student's t-distribution p-values without depending on an external
+/// stats crate, the same way [`log_gamma`] stands in for a gamma-function
+/// dependency.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let bt = (log_gamma(a + b) - log_gamma(a) - log_gamma(b)
+        + a * x.ln()
+        + b * (1.0 - x).ln())
+    .exp();
+
+    if x < (a + 1.0) / (a + b + 2.0) {
+        bt * betacf(x, a, b) / a
+    } else {
+        1.0 - bt * betacf(1.0 - x, b, a) / b
+    }
+}
+
+/// Continued-fraction evaluation used by [`incomplete_beta`]
+fn betacf(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERS: usize = 200;
+    const EPS: f64 = 3e-12;
+    const FPMIN: f64 = 1e-300;
+
+    let qab = a + b;
+    let qap = a + 1.0;
+    let qam = a - 1.0;
+
+    let mut c = 1.0f64;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < FPMIN {
+        d = FPMIN;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERS {
+        let m_f = m as f64;
+        let m2 = 2.0 * m_f;
+
+        let aa = m_f * (b - m_f) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let aa = -(a + m_f) * (qab + m_f) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < FPMIN {
+            d = FPMIN;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < FPMIN {
+            c = FPMIN;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+
+        if (del - 1.0).abs() < EPS {
+            break;
+        }
+    }
+
+    h
+}
+
+/// Two-tailed p-value for Student's t-distribution with `df` degrees of
+/// freedom
+fn t_distribution_p(t: f64, df: f64) -> f64 {
+    let x = df / (df + t * t);
+    incomplete_beta(x, df / 2.0, 0.5)
+}
+
+/// One-sample, two-tailed t-test of the null hypothesis that the mean of
+/// `values` equals `mu` (e.g. the log2(4) engagement threshold used for
+/// ligandability sites), returning a p-value. `values` must have at
+/// least two entries and nonzero variance to return a meaningful result.
+pub fn one_sample_t_test(values: &[f64], mu: f64) -> Option<f64> {
+    let n = values.len();
+    if n < 2 {
+        return None;
+    }
+    let n_f = n as f64;
+    let mean = values.iter().sum::<f64>() / n_f;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n_f - 1.0);
+    if variance <= 0.0 {
+        return Some(if mean == mu { 1.0 } else { 0.0 });
+    }
+
+    let se = (variance / n_f).sqrt();
+    let t = (mean - mu) / se;
+    Some(t_distribution_p(t, n_f - 1.0))
+}
+
+/// Benjamini-Hochberg FDR correction.
+///
+/// Sorts by ascending p-value, scales each by `m / rank`, then enforces
+/// monotonicity by taking a running minimum from the largest rank down,
+/// and returns q-values in the same order as the input.
+pub fn benjamini_hochberg(pvalues: &[f64]) -> Vec<f64> {
+    let m = pvalues.len();
+    let mut ranked = pvalues.iter().copied().enumerate().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    let mut qvalues = vec![0.0; m];
+    let mut running_min = 1.0f64;
+    for (rank, (orig_idx, p)) in ranked.iter().enumerate().rev() {
+        let scaled = p * m as f64 / (rank as f64 + 1.0);
+        running_min = running_min.min(scaled);
+        qvalues[*orig_idx] = running_min.min(1.0);
+    }
+    qvalues
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hypergeometric_matches_known_value() {
+        // Classic urn example: N=20, K=7, n=12, P(X >= 5)
+        let p = hypergeometric_sf(5, 7, 20, 12);
+        assert!((p - 0.3916).abs() < 0.001);
+    }
+
+    #[test]
+    fn t_test_rejects_when_mean_is_far_from_mu() {
+        let values = vec![4.0, 4.1, 3.9, 4.05, 3.95];
+        let p = one_sample_t_test(&values, 0.0).unwrap();
+        assert!(p < 0.001, "expected a tiny p-value, got {}", p);
+    }
+
+    #[test]
+    fn t_test_fails_to_reject_when_mean_matches_mu() {
+        let values = vec![1.9, 2.1, 2.0, 1.95, 2.05];
+        let p = one_sample_t_test(&values, 2.0).unwrap();
+        assert!(p > 0.5, "expected a large p-value, got {}", p);
+    }
+
+    #[test]
+    fn t_test_needs_at_least_two_values() {
+        assert_eq!(one_sample_t_test(&[1.0], 0.0), None);
+    }
+
+    #[test]
+    fn benjamini_hochberg_is_monotone_and_bounded() {
+        let p = vec![0.01, 0.04, 0.03, 0.5, 0.001];
+        let q = benjamini_hochberg(&p);
+        assert_eq!(q.len(), p.len());
+        for &x in &q {
+            assert!(x >= 0.0 && x <= 1.0);
+        }
+        // Smallest p-value should have the smallest (or equal) q-value
+        assert!(q[4] <= q[3]);
+    }
+}
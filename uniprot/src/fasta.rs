@@ -1,11 +1,35 @@
 //! Utilities for loading genomic information
+use flate2::read::MultiGzDecoder;
 use memchr::{memchr_iter, Memchr};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, prelude::*};
 use std::path::Path;
 use std::str;
 
+/// Magic bytes at the start of every gzip (and therefore BGZF, which is
+/// just gzip with a block structure) member.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read all of `path` into memory, transparently decompressing it first if
+/// it's gzip/bgzip-compressed. BGZF streams are ordinary concatenated gzip
+/// members under the hood, which [`MultiGzDecoder`] already decodes as one
+/// continuous stream.
+fn read_to_string_gz<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut f = File::open(path)?;
+    let mut magic = [0u8; 2];
+    let n = f.read(&mut magic)?;
+    f.rewind()?;
+
+    let mut buf = String::new();
+    if n == 2 && magic == GZIP_MAGIC {
+        MultiGzDecoder::new(f).read_to_string(&mut buf)?;
+    } else {
+        f.read_to_string(&mut buf)?;
+    }
+    Ok(buf)
+}
+
 struct Pitchfork<'a> {
     pos: usize,
     haystack: &'a [u8],
@@ -43,66 +67,770 @@ impl<'a> Iterator for Pitchfork<'a> {
     }
 }
 
+/// How to extract an accession from a FASTA header line (everything after
+/// the leading `>`). [`Fasta::open`] auto-detects which of these a
+/// database uses from its first header; [`Fasta::open_with`] lets a
+/// caller pin one down explicitly instead, including a custom closure via
+/// [`HeaderParser::Custom`] for conventions not listed here.
+pub enum HeaderParser {
+    /// UniProt-style `db|ACCESSION|ENTRY_NAME ...`, e.g. `sp|P04637|P53_HUMAN`.
+    UniProt,
+    /// NCBI-style `ACCESSION.VERSION description ...`, e.g.
+    /// `NP_000537.3 cellular tumor antigen p53`.
+    Ncbi,
+    /// Ensembl-style ` ID attr:value attr:value ...`; the accession is the
+    /// first whitespace-delimited token, stripped of any `type:` prefix
+    /// (e.g. `gene:ENSG00000141510` -> `ENSG00000141510`).
+    Ensembl,
+    /// Use the entire header line, verbatim, as the accession.
+    WholeLine,
+    /// Caller-supplied extraction function; return `None` to reject a
+    /// header as malformed.
+    Custom(Box<dyn Fn(&str) -> Option<String>>),
+}
+
+impl HeaderParser {
+    fn parse(&self, header: &str) -> Option<String> {
+        match self {
+            HeaderParser::UniProt => header.split('|').nth(1).map(String::from),
+            HeaderParser::Ncbi => header.split_whitespace().next().map(String::from),
+            HeaderParser::Ensembl => {
+                let token = header.split_whitespace().next()?;
+                Some(
+                    token
+                        .rsplit_once(':')
+                        .map(|(_, acc)| acc)
+                        .unwrap_or(token)
+                        .to_string(),
+                )
+            }
+            HeaderParser::WholeLine => Some(header.to_string()),
+            HeaderParser::Custom(f) => f(header),
+        }
+    }
+
+    /// Guess which convention `header` (a record's header line, without
+    /// the leading `>`) uses: UniProt if it's `|`-delimited, otherwise the
+    /// first whitespace-delimited token, which also covers NCBI- and
+    /// Ensembl-style headers as well as bare accessions.
+    fn detect(header: &str) -> HeaderParser {
+        if header.contains('|') {
+            HeaderParser::UniProt
+        } else {
+            HeaderParser::Ncbi
+        }
+    }
+}
+
+/// How to recognize a decoy entry in a target-decoy FASTA database, and
+/// whether to keep or drop decoys on load.
+pub struct DecoyConfig {
+    pub marker: DecoyMarker,
+    pub keep: bool,
+}
+
+/// Where the decoy marker must appear in a header for [`DecoyConfig`] to
+/// classify it as a decoy.
+pub enum DecoyMarker {
+    /// Header starts with this string, e.g. `"decoy_"`.
+    Prefix(String),
+    /// Header contains this string anywhere, e.g. `"Reverse"`.
+    Contains(String),
+}
+
+impl DecoyMarker {
+    fn is_decoy(&self, header: &str) -> bool {
+        match self {
+            DecoyMarker::Prefix(p) => header.starts_with(p.as_str()),
+            DecoyMarker::Contains(n) => header.contains(n.as_str()),
+        }
+    }
+}
+
+impl Default for DecoyConfig {
+    /// This crate's historical convention: any header containing
+    /// `"Reverse"` is a decoy, and decoys are dropped on load.
+    fn default() -> Self {
+        DecoyConfig {
+            marker: DecoyMarker::Contains(String::from("Reverse")),
+            keep: false,
+        }
+    }
+}
+
+/// Strategy for synthesizing a decoy sequence in [`Fasta::append_decoys`].
+pub enum DecoyStrategy {
+    /// Reverse the sequence, keeping the C-terminal residue fixed so a
+    /// tryptic K/R terminus survives into the decoy.
+    Reversal,
+    /// Composition-preserving shuffle of the sequence's residues, seeded
+    /// deterministically from the accession so runs are reproducible.
+    Shuffle,
+}
+
 #[derive(Debug, Clone)]
 pub struct Fasta {
     pub map: HashMap<String, String>,
+    /// Accessions classified as decoys by the [`DecoyConfig`] this
+    /// database was loaded with, or added by [`Fasta::append_decoys`].
+    /// Only populated when decoys were kept rather than dropped.
+    pub decoys: HashSet<String>,
 }
 
 impl Fasta {
-    /// Build a fasta database
+    /// Build a fasta database, auto-detecting which header convention it
+    /// uses from its first record and dropping decoys per
+    /// [`DecoyConfig::default`]. Use [`Fasta::open_with`] to control
+    /// either of those explicitly instead of guessing/defaulting.
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Fasta> {
-        let mut buf = String::new();
-        File::open(path)?.read_to_string(&mut buf)?;
+        let buf = read_to_string_gz(path)?;
+        let header = Self::first_header(&buf)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty FASTA file"))?;
+        Self::parse(&buf, &HeaderParser::detect(header), &DecoyConfig::default())
+    }
 
+    /// Like [`Fasta::open`], but parses accessions out of each header with
+    /// `parser`, and classifies/keeps-or-drops decoys per `decoys`.
+    pub fn open_with<P: AsRef<Path>>(
+        path: P,
+        parser: HeaderParser,
+        decoys: DecoyConfig,
+    ) -> io::Result<Fasta> {
+        let buf = read_to_string_gz(path)?;
+        Self::parse(&buf, &parser, &decoys)
+    }
+
+    fn first_header(buf: &str) -> Option<&str> {
+        buf.lines().find_map(|l| l.strip_prefix('>'))
+    }
+
+    fn parse(buf: &str, parser: &HeaderParser, decoys: &DecoyConfig) -> io::Result<Fasta> {
         let mut map = HashMap::new();
-        let mut iter = Pitchfork::new('\n' as u8, buf.as_bytes());
-        let mut last_id = iter.next().unwrap();
+        let mut decoy_set = HashSet::new();
+        let mut iter = Pitchfork::new(b'\n', buf.as_bytes());
+        let mut last_header = iter
+            .next()
+            .and_then(|line| str::from_utf8(line).ok())
+            .and_then(|line| line.strip_prefix('>'));
         let mut s = String::new();
 
         for line in iter {
-            if line.len() == 0 {
+            if line.is_empty() {
                 continue;
             }
-            // dbg!(str::from_utf8(line).unwrap());
-            if line[0] == '>' as u8 {
-                if s != "" {
-                    let id = str::from_utf8(last_id).unwrap();
-                    if id.contains("Reverse") {
-                        s.clear();
-                        continue;
-                    }
-                    let acc = id.split('|').skip(1).next().unwrap().into();
-                    map.insert(acc, std::mem::replace(&mut s, String::new()));
-                    last_id = line;
-                // s.clear();
-                } else {
-                    last_id = line;
+            if line[0] == b'>' {
+                if !s.is_empty() {
+                    Self::insert_record(
+                        &mut map,
+                        &mut decoy_set,
+                        last_header,
+                        parser,
+                        decoys,
+                        std::mem::take(&mut s),
+                    )?;
                 }
+                last_header = str::from_utf8(line).ok().and_then(|l| l.strip_prefix('>'));
             } else {
-                s.push_str(str::from_utf8(line).unwrap());
+                s.push_str(
+                    str::from_utf8(line)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                );
             }
         }
-        Ok(Fasta { map })
+        if !s.is_empty() {
+            Self::insert_record(&mut map, &mut decoy_set, last_header, parser, decoys, s)?;
+        }
+
+        Ok(Fasta {
+            map,
+            decoys: decoy_set,
+        })
+    }
+
+    /// Resolve `header` into an accession via `parser` and insert
+    /// `sequence` under it, classifying and keeping-or-dropping decoys
+    /// per `decoys`.
+    fn insert_record(
+        map: &mut HashMap<String, String>,
+        decoy_set: &mut HashSet<String>,
+        header: Option<&str>,
+        parser: &HeaderParser,
+        decoys: &DecoyConfig,
+        sequence: String,
+    ) -> io::Result<()> {
+        let header = header.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "FASTA sequence with no preceding header",
+            )
+        })?;
+        let is_decoy = decoys.marker.is_decoy(header);
+        if is_decoy && !decoys.keep {
+            return Ok(());
+        }
+        let acc = parser.parse(header).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed FASTA header: {:?}", header),
+            )
+        })?;
+        if is_decoy {
+            decoy_set.insert(acc.clone());
+        }
+        if map.insert(acc.clone(), sequence).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("duplicate accession in FASTA database: {}", acc),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Is `acc` a decoy? Only meaningful when this database was loaded
+    /// with `DecoyConfig { keep: true, .. }` or has had decoys appended.
+    pub fn is_decoy(&self, acc: &str) -> bool {
+        self.decoys.contains(acc)
+    }
+
+    /// Generate a decoy for every target accession currently in
+    /// `self.map` and insert it under `prefix`-prepended accession, e.g.
+    /// `Reverse_P04637`. Existing decoys are skipped, so repeated calls
+    /// don't decoy decoys.
+    pub fn append_decoys(&mut self, strategy: DecoyStrategy, prefix: &str) {
+        let targets: Vec<(String, String)> = self
+            .map
+            .iter()
+            .filter(|(acc, _)| !self.decoys.contains(*acc))
+            .map(|(acc, seq)| (acc.clone(), seq.clone()))
+            .collect();
+
+        for (acc, seq) in targets {
+            let decoy_seq = match strategy {
+                DecoyStrategy::Reversal => Self::reverse_keep_terminus(&seq),
+                DecoyStrategy::Shuffle => Self::shuffle_composition(&seq, &acc),
+            };
+            let decoy_acc = format!("{}{}", prefix, acc);
+            self.map.insert(decoy_acc.clone(), decoy_seq);
+            self.decoys.insert(decoy_acc);
+        }
+    }
+
+    fn reverse_keep_terminus(seq: &str) -> String {
+        let mut chars: Vec<char> = seq.chars().collect();
+        let last = match chars.pop() {
+            Some(c) => c,
+            None => return String::new(),
+        };
+        chars.reverse();
+        chars.push(last);
+        chars.into_iter().collect()
+    }
+
+    /// Composition-preserving Fisher-Yates shuffle, driven by a xorshift
+    /// PRNG seeded from `seed` (the accession) so the same database always
+    /// produces the same decoys.
+    fn shuffle_composition(seq: &str, seed: &str) -> String {
+        let mut state = seed
+            .bytes()
+            .fold(0x9E3779B97F4A7C15u64, |acc, b| {
+                (acc ^ b as u64).wrapping_mul(0x100000001B3)
+            })
+            .max(1);
+
+        let mut chars: Vec<char> = seq.chars().collect();
+        for i in (1..chars.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let j = (state as usize) % (i + 1);
+            chars.swap(i, j);
+        }
+        chars.into_iter().collect()
     }
 
     pub fn sequence(&self, acc: &str) -> Option<&String> {
         self.map.get(acc)
     }
 
+    /// Locate the first modified residue's absolute position in `acc`'s
+    /// parent sequence - see [`Fasta::assign_all`] for every match, every
+    /// modification site, I/L-agnostic matching, and the distinction
+    /// between "accession missing" and "no match".
     pub fn assign(&self, acc: &str, seq: &str) -> Option<usize> {
+        self.assign_all(acc, seq).into_iter().next()
+    }
+
+    /// Like [`Fasta::assign`], but:
+    /// - returns every start position the peptide matches at, not just
+    ///   the first
+    /// - matches I/L-agnostically, since MS/MS can't distinguish
+    ///   leucine from isoleucine
+    /// - handles peptides with more than one `*` modification marker,
+    ///   returning the absolute position of every marked residue in
+    ///   every match
+    ///
+    /// Returns an empty `Vec` both when `acc` has no parent sequence and
+    /// when the peptide simply isn't found in it - use [`Fasta::sequence`]
+    /// first if the caller needs to tell those two cases apart.
+    pub fn assign_all(&self, acc: &str, seq: &str) -> Vec<usize> {
         // Handle tryptic cleavage sites
-        let peptide = if seq.contains(".") {
-            seq.split(".").skip(1).next()?
+        let peptide = match seq.contains('.') {
+            true => match seq.split('.').nth(1) {
+                Some(p) => p,
+                None => return Vec::new(),
+            },
+            false => seq,
+        };
+
+        let primary = match self.map.get(acc) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+
+        // Record each `*`'s residue index in the unmodified peptide, then
+        // strip all markers to form the search needle.
+        let mut site_offsets = Vec::new();
+        let mut needle = String::with_capacity(peptide.len());
+        for c in peptide.chars() {
+            if c == '*' {
+                site_offsets.push(needle.chars().count());
+            } else {
+                needle.push(c);
+            }
+        }
+
+        let matches = Self::find_all(&Self::canonicalize(primary), &Self::canonicalize(&needle));
+
+        if site_offsets.is_empty() {
+            matches
+        } else {
+            matches
+                .into_iter()
+                .flat_map(|match_start| {
+                    site_offsets.iter().map(move |&rel| match_start + rel)
+                })
+                .collect()
+        }
+    }
+
+    /// Replace I/L with a shared sentinel so leucine/isoleucine ambiguity
+    /// (indistinguishable by mass in MS/MS) doesn't block a match.
+    fn canonicalize(s: &str) -> String {
+        s.chars()
+            .map(|c| if c == 'I' || c == 'L' { 'J' } else { c })
+            .collect()
+    }
+
+    /// Every (possibly overlapping) byte offset at which `needle` occurs
+    /// in `haystack`.
+    fn find_all(haystack: &str, needle: &str) -> Vec<usize> {
+        if needle.is_empty() {
+            return Vec::new();
+        }
+        let mut positions = Vec::new();
+        let mut start = 0;
+        while let Some(idx) = haystack[start..].find(needle) {
+            positions.push(start + idx);
+            start += idx + 1;
+        }
+        positions
+    }
+}
+
+/// One record of a samtools-style `.fai` FASTA index: NAME, LENGTH (bases),
+/// OFFSET (byte offset of the first base), LINEBASES (bases per line), and
+/// LINEWIDTH (bytes per line, including the newline).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaiRecord {
+    pub name: String,
+    pub length: u64,
+    pub offset: u64,
+    pub linebases: u64,
+    pub linewidth: u64,
+}
+
+/// A FASTA database that resolves subsequences on demand via a `.fai`
+/// index instead of holding every sequence resident, for genome- or
+/// large-proteome-scale databases where [`Fasta::open`]'s in-memory
+/// `HashMap` is prohibitive.
+pub struct IndexedFasta {
+    path: std::path::PathBuf,
+    index: BTreeMap<String, FaiRecord>,
+}
+
+impl IndexedFasta {
+    /// Open `path`, auto-detecting which header convention it uses from
+    /// its first record (see [`HeaderParser::detect`]), and loading its
+    /// `.fai` sidecar if one exists next to it, or building and caching
+    /// one otherwise. Use [`IndexedFasta::open_with`] to pin the header
+    /// convention down explicitly instead of guessing.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<IndexedFasta> {
+        let path = path.as_ref().to_path_buf();
+        let header = Self::first_header(&path)?;
+        Self::open_with(path, HeaderParser::detect(&header))
+    }
+
+    /// Like [`IndexedFasta::open`], but parses accessions out of each
+    /// header with `parser` rather than auto-detecting.
+    pub fn open_with<P: AsRef<Path>>(path: P, parser: HeaderParser) -> io::Result<IndexedFasta> {
+        let path = path.as_ref().to_path_buf();
+        let fai_path = Self::fai_path(&path);
+
+        let index = if fai_path.exists() {
+            Self::read_index(&fai_path)?
         } else {
-            seq
+            let index = Self::build_index(&path, &parser)?;
+            Self::write_index(&fai_path, &index)?;
+            index
         };
-        let primary = self.map.get(acc)?;
-        match peptide.find('*') {
-            Some(offset) => {
-                let needle = peptide.chars().filter(|&c| c != '*').collect::<String>();
-                primary.find(&needle).map(|idx| idx + offset)
+
+        Ok(IndexedFasta { path, index })
+    }
+
+    /// Read just enough of `path` to find its first header line, without
+    /// loading the whole file - see [`Fasta::first_header`] for the
+    /// in-memory equivalent used by [`Fasta::open`].
+    fn first_header(path: &Path) -> io::Result<String> {
+        let f = File::open(path)?;
+        let mut reader = io::BufReader::new(f);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let bytes_read = reader.read_line(&mut line)?;
+            if bytes_read == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "empty FASTA file"));
+            }
+            if let Some(header) = line.strip_prefix('>') {
+                return Ok(header.trim_end().to_string());
             }
-            None => primary.find(peptide),
         }
     }
+
+    fn fai_path(path: &Path) -> std::path::PathBuf {
+        let mut s = path.as_os_str().to_owned();
+        s.push(".fai");
+        std::path::PathBuf::from(s)
+    }
+
+    /// Scan `path` once to build its `.fai` index, extracting each record's
+    /// name with `parser` rather than assuming UniProt's `db|ACCESSION|NAME`
+    /// layout, without writing the index to disk - see [`IndexedFasta::open`]
+    /// for the cache-aware entry point.
+    pub fn build_index<P: AsRef<Path>>(
+        path: P,
+        parser: &HeaderParser,
+    ) -> io::Result<BTreeMap<String, FaiRecord>> {
+        let f = File::open(path)?;
+        let mut reader = io::BufReader::new(f);
+        let mut index = BTreeMap::new();
+
+        // name, length, offset, linebases, linewidth of the record currently
+        // being scanned
+        let mut current: Option<(String, u64, u64, u64, u64)> = None;
+        let mut offset: u64 = 0;
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let bytes_read = reader.read_line(&mut line)? as u64;
+            if bytes_read == 0 {
+                break;
+            }
+
+            if line.starts_with('>') {
+                if let Some((name, length, rec_offset, linebases, linewidth)) = current.take() {
+                    index.insert(
+                        name.clone(),
+                        FaiRecord {
+                            name,
+                            length,
+                            offset: rec_offset,
+                            linebases,
+                            linewidth,
+                        },
+                    );
+                }
+                let header = line[1..].trim_end().to_string();
+                let name = parser.parse(&header).unwrap_or_else(|| header.clone());
+                current = Some((name, 0, offset + bytes_read, 0, 0));
+            } else if let Some((_, ref mut length, _, ref mut linebases, ref mut linewidth)) =
+                current
+            {
+                let bases = line.trim_end_matches(['\n', '\r']).len() as u64;
+                if *linebases == 0 {
+                    *linebases = bases;
+                    *linewidth = bytes_read;
+                }
+                *length += bases;
+            }
+
+            offset += bytes_read;
+        }
+
+        if let Some((name, length, rec_offset, linebases, linewidth)) = current.take() {
+            index.insert(
+                name.clone(),
+                FaiRecord {
+                    name,
+                    length,
+                    offset: rec_offset,
+                    linebases,
+                    linewidth,
+                },
+            );
+        }
+
+        Ok(index)
+    }
+
+    fn read_index(path: &Path) -> io::Result<BTreeMap<String, FaiRecord>> {
+        let f = File::open(path)?;
+        let mut index = BTreeMap::new();
+        for line in io::BufReader::new(f).lines() {
+            let line = line?;
+            let mut fields = line.split('\t');
+            let name = fields.next().unwrap_or_default().to_string();
+            let length = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+            let offset = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+            let linebases = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+            let linewidth = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+            index.insert(
+                name.clone(),
+                FaiRecord {
+                    name,
+                    length,
+                    offset,
+                    linebases,
+                    linewidth,
+                },
+            );
+        }
+        Ok(index)
+    }
+
+    fn write_index(path: &Path, index: &BTreeMap<String, FaiRecord>) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        let mut records: Vec<&FaiRecord> = index.values().collect();
+        records.sort_by_key(|r| r.offset);
+        for r in records {
+            writeln!(
+                f,
+                "{}\t{}\t{}\t{}\t{}",
+                r.name, r.length, r.offset, r.linebases, r.linewidth
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the `[start, end)` subsequence of `acc`, seeking directly to
+    /// its byte position rather than holding the whole database resident.
+    pub fn fetch(&self, acc: &str, start: usize, end: usize) -> io::Result<String> {
+        let record = self.index.get(acc).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not present in .fai index", acc),
+            )
+        })?;
+
+        let linebases = record.linebases.max(1);
+        let linewidth = record.linewidth.max(1);
+        let end = (end as u64).min(record.length);
+        let start = (start as u64).min(end);
+        let span = end - start;
+        if span == 0 {
+            return Ok(String::new());
+        }
+
+        let byte_start = record.offset + (start / linebases) * linewidth + (start % linebases);
+        let newline_width = linewidth - linebases;
+        let lines_spanned = (start % linebases + span) / linebases + 1;
+        let byte_len = span + lines_spanned * newline_width;
+
+        let mut f = File::open(&self.path)?;
+        f.seek(io::SeekFrom::Start(byte_start))?;
+        let mut raw = Vec::new();
+        f.take(byte_len).read_to_end(&mut raw)?;
+
+        Ok(raw
+            .into_iter()
+            .filter(|&b| b != b'\n' && b != b'\r')
+            .map(|b| b as char)
+            .take(span as usize)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_fasta(contents: &str, name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("test-{}-{}.fasta", std::process::id(), name));
+        let mut f = File::create(&path).unwrap();
+        f.write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn header_parser_detect_picks_uniprot_for_pipe_delimited_headers() {
+        assert!(matches!(
+            HeaderParser::detect("sp|P04637|P53_HUMAN"),
+            HeaderParser::UniProt
+        ));
+    }
+
+    #[test]
+    fn header_parser_detect_falls_back_to_ncbi_for_bare_headers() {
+        assert!(matches!(
+            HeaderParser::detect("NP_000537.3 cellular tumor antigen p53"),
+            HeaderParser::Ncbi
+        ));
+    }
+
+    #[test]
+    fn assign_all_finds_overlapping_matches() {
+        // "AAAA" occurs at offsets 0 and 1 in "AAAAA" (overlapping)
+        let mut map = HashMap::new();
+        map.insert(String::from("P1"), String::from("AAAAA"));
+        let fasta = Fasta {
+            map,
+            decoys: HashSet::new(),
+        };
+        let mut positions = fasta.assign_all("P1", "AAAA");
+        positions.sort();
+        assert_eq!(positions, vec![0, 1]);
+    }
+
+    #[test]
+    fn assign_all_matches_i_l_ambiguously() {
+        let mut map = HashMap::new();
+        map.insert(String::from("P1"), String::from("MEHQILL"));
+        let fasta = Fasta {
+            map,
+            decoys: HashSet::new(),
+        };
+        // peptide uses L where the parent sequence has I - still a match
+        let positions = fasta.assign_all("P1", "MEHQLLL");
+        assert_eq!(positions, vec![0]);
+    }
+
+    #[test]
+    fn assign_all_locates_every_modification_marker() {
+        let mut map = HashMap::new();
+        map.insert(String::from("P1"), String::from("AAMEHQLLAA"));
+        let fasta = Fasta {
+            map,
+            decoys: HashSet::new(),
+        };
+        let mut positions = fasta.assign_all("P1", "ME*HQL*L");
+        positions.sort();
+        // peptide "MEHQLL" starts at offset 2 in the parent sequence; a
+        // `*` follows the residue it marks, so the two markers land on
+        // the 2nd (E) and 5th (L) residues of the stripped needle, at
+        // 2 + 2 = 4 and 2 + 5 = 7.
+        assert_eq!(positions, vec![4, 7]);
+    }
+
+    #[test]
+    fn assign_all_missing_accession_is_empty() {
+        let fasta = Fasta {
+            map: HashMap::new(),
+            decoys: HashSet::new(),
+        };
+        assert_eq!(fasta.assign_all("P1", "MEHQLL"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn reverse_keep_terminus_preserves_last_residue() {
+        assert_eq!(Fasta::reverse_keep_terminus("MEHQLLK"), "LLQHEMK");
+        assert_eq!(Fasta::reverse_keep_terminus(""), "");
+    }
+
+    #[test]
+    fn shuffle_composition_preserves_residue_counts_and_is_deterministic() {
+        let seq = "MEHQLLKAAAK";
+        let shuffled = Fasta::shuffle_composition(seq, "P12345");
+
+        let mut original: Vec<char> = seq.chars().collect();
+        let mut result: Vec<char> = shuffled.chars().collect();
+        original.sort();
+        result.sort();
+        assert_eq!(original, result);
+
+        // same seed -> same shuffle every time
+        assert_eq!(shuffled, Fasta::shuffle_composition(seq, "P12345"));
+        // a different seed (accession) gives a different shuffle
+        assert_ne!(shuffled, Fasta::shuffle_composition(seq, "Q99999"));
+    }
+
+    #[test]
+    fn fetch_spans_a_single_line() {
+        let path = write_fasta(">P1\nMEHQLLKAAAK\n", "single-line");
+        let index = IndexedFasta::build_index(&path, &HeaderParser::UniProt).unwrap();
+        let indexed = IndexedFasta {
+            path: path.clone(),
+            index,
+        };
+        assert_eq!(indexed.fetch("P1", 0, 4).unwrap(), "MEHQ");
+        assert_eq!(indexed.fetch("P1", 4, 11).unwrap(), "LLKAAAK");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn fetch_spans_a_line_boundary() {
+        // 60 bases per line (samtools default); request a span that
+        // crosses from the first line into the second.
+        let line1 = "A".repeat(60);
+        let line2 = "C".repeat(20);
+        let contents = format!(">P1\n{}\n{}\n", line1, line2);
+        let path = write_fasta(&contents, "line-boundary");
+        let index = IndexedFasta::build_index(&path, &HeaderParser::UniProt).unwrap();
+        let indexed = IndexedFasta {
+            path: path.clone(),
+            index,
+        };
+
+        // offsets 55..65 span the last 5 bases of line 1 and the first 5 of line 2
+        assert_eq!(indexed.fetch("P1", 55, 65).unwrap(), "AAAAACCCCC");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn fetch_spans_multiple_lines() {
+        let line1 = "A".repeat(60);
+        let line2 = "C".repeat(60);
+        let line3 = "G".repeat(10);
+        let contents = format!(">P1\n{}\n{}\n{}\n", line1, line2, line3);
+        let path = write_fasta(&contents, "multi-line");
+        let index = IndexedFasta::build_index(&path, &HeaderParser::UniProt).unwrap();
+        let indexed = IndexedFasta {
+            path: path.clone(),
+            index,
+        };
+
+        // spans all of line 2 plus a bit of line 1 and line 3
+        let fetched = indexed.fetch("P1", 58, 122).unwrap();
+        assert_eq!(fetched.len(), 64);
+        assert!(fetched.starts_with("AA"));
+        assert!(fetched.ends_with("GG"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn build_index_uses_header_parser_instead_of_hardcoded_pipe() {
+        // NCBI-style header with no UniProt `|`-delimited accession
+        let path = write_fasta(">NP_000537.3 cellular tumor antigen p53\nMEHQLLK\n", "ncbi");
+        let index = IndexedFasta::build_index(&path, &HeaderParser::Ncbi).unwrap();
+        assert!(index.contains_key("NP_000537.3"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn open_auto_detects_header_convention() {
+        let path = write_fasta(">NP_000537.3 cellular tumor antigen p53\nMEHQLLK\n", "open-detect");
+        let indexed = IndexedFasta::open(&path).unwrap();
+        assert_eq!(indexed.fetch("NP_000537.3", 0, 4).unwrap(), "MEHQ");
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(IndexedFasta::fai_path(&path)).ok();
+    }
 }
@@ -2,6 +2,8 @@
 //! The Keywords represent molecular function ontologies
 //!
 
+use crate::obo::GoDag;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
@@ -9,11 +11,15 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
 
-#[derive(Clone, Default, Debug, PartialEq)]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Annotation {
     pub kw: HashMap<String, String>,
     pub go: HashMap<String, String>,
     pub enz: HashSet<String>,
+    /// Directly-annotated GO term IDs per accession, as parsed from `DR   GO`
+    /// lines. Kept separate from `go` (which holds the human-readable term
+    /// descriptions used for display) since the DAG needs real term IDs.
+    pub go_ids: HashMap<String, Vec<String>>,
 }
 
 impl Annotation {
@@ -26,6 +32,43 @@ impl Annotation {
     pub fn enzyme(&self, accession: &str) -> bool {
         self.enz.contains(accession)
     }
+
+    /// Return the directly-annotated GO term IDs for `accession`
+    pub fn go_ids(&self, accession: &str) -> &[String] {
+        self.go_ids
+            .get(accession)
+            .map(|v| v.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Propagate `accession`'s directly-annotated GO terms up `dag`,
+    /// returning the union of the direct terms and all of their ancestors.
+    pub fn propagate_go(&self, accession: &str, dag: &GoDag) -> HashSet<String> {
+        dag.propagate(self.go_ids(accession).iter().map(|s| s.as_str()))
+    }
+}
+
+/// Load `uniprot_sprot.dat`, caching the parsed result as a bincode
+/// sidecar `<path>.bin`. If the cache exists and is at least as new as
+/// the source file, it's deserialized directly; otherwise the source
+/// is (re-)parsed and the cache is written.
+pub fn load_cached<T: AsRef<Path>>(path: T) -> io::Result<Annotation> {
+    let source = path.as_ref();
+    let cache = crate::cache_path(source);
+
+    if crate::cache_is_fresh(source, &cache) {
+        if let Ok(bytes) = std::fs::read(&cache) {
+            if let Ok(ann) = bincode::deserialize::<Annotation>(&bytes) {
+                return Ok(ann);
+            }
+        }
+    }
+
+    let ann = load(source)?;
+    if let Ok(bytes) = bincode::serialize(&ann) {
+        let _ = std::fs::write(&cache, bytes);
+    }
+    Ok(ann)
 }
 
 pub fn load<T: AsRef<Path>>(path: T) -> io::Result<Annotation> {
@@ -35,6 +78,7 @@ pub fn load<T: AsRef<Path>>(path: T) -> io::Result<Annotation> {
     let mut current_ac = String::default();
     let mut kw = String::default();
     let mut go = String::default();
+    let mut go_ids: Vec<String> = Vec::new();
 
     let mut ann = Annotation::default();
 
@@ -43,16 +87,21 @@ pub fn load<T: AsRef<Path>>(path: T) -> io::Result<Annotation> {
 
         if line.starts_with("AC") {
             ann.kw.insert(current_ac.clone(), kw);
-            ann.go.insert(current_ac, go);
+            ann.go.insert(current_ac.clone(), go);
+            ann.go_ids.insert(current_ac, go_ids);
 
             kw = String::default();
             go = String::default();
+            go_ids = Vec::new();
             current_ac = line[5..11].into();
         } else if line.starts_with("KW") {
             kw.push_str(line.trim_start_matches("KW   "));
         } else if line.starts_with("DR   GO") {
-            let s = line.split(';');
-            go.push_str(s.skip(2).next().unwrap_or_default());
+            let mut s = line.split(';');
+            if let Some(id) = s.nth(1) {
+                go_ids.push(id.trim().to_string());
+            }
+            go.push_str(line.split(';').skip(2).next().unwrap_or_default());
         } else if line.starts_with("DE            EC=") {
             ann.enz.insert(current_ac.clone());
         }
@@ -27,12 +27,14 @@
 //!
 
 pub mod kw;
+pub mod obo;
 
 use memchr::{memchr_iter, Memchr};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{self, prelude::*};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str;
 
 pub mod fasta;
@@ -74,7 +76,7 @@ impl<'a> Iterator for Pitchfork<'a> {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 /// Represents an entry in the locally-stored UniprotKB database
 pub struct Entry {
     /// Uniprot accession identifier
@@ -85,12 +87,29 @@ pub struct Entry {
     pub sequence: String,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 /// Wraps a hashtable
 pub struct Uniprot {
     inner: HashMap<String, Entry>,
 }
 
+/// Sidecar cache path for a source database file
+pub(crate) fn cache_path<T: AsRef<Path>>(path: T) -> PathBuf {
+    let mut s = path.as_ref().as_os_str().to_owned();
+    s.push(".bin");
+    PathBuf::from(s)
+}
+
+/// `true` if `cache` exists and is at least as new as `source`
+pub(crate) fn cache_is_fresh(source: &Path, cache: &Path) -> bool {
+    let source_mtime = source.metadata().and_then(|m| m.modified()).ok();
+    let cache_mtime = cache.metadata().and_then(|m| m.modified()).ok();
+    match (source_mtime, cache_mtime) {
+        (Some(s), Some(c)) => c >= s,
+        _ => false,
+    }
+}
+
 impl Uniprot {
     /// Load a UniprotKB file to build a [`Uniprot`] object
     ///
@@ -182,6 +201,33 @@ impl Uniprot {
         Ok(Uniprot { inner })
     }
 
+    /// Load a UniprotKB file, transparently caching the parsed result as
+    /// a bincode sidecar `<path>.bin` next to it.
+    ///
+    /// If a cache file exists and its mtime is at least as new as the
+    /// source file's, it's deserialized directly instead of re-parsing
+    /// the (potentially very large) flat file; otherwise the source is
+    /// parsed as usual and the cache is (re)written.
+    #[allow(deprecated)]
+    pub fn load_cached<T: AsRef<Path>>(path: T) -> io::Result<Self> {
+        let source = path.as_ref();
+        let cache = cache_path(source);
+
+        if cache_is_fresh(source, &cache) {
+            if let Ok(bytes) = std::fs::read(&cache) {
+                if let Ok(db) = bincode::deserialize::<Uniprot>(&bytes) {
+                    return Ok(db);
+                }
+            }
+        }
+
+        let db = Self::load(source)?;
+        if let Ok(bytes) = bincode::serialize(&db) {
+            let _ = std::fs::write(&cache, bytes);
+        }
+        Ok(db)
+    }
+
     /// Search a [`Uniprot`] database object by Uniprot accession
     ///
     /// # Example
@@ -200,19 +246,79 @@ impl Uniprot {
     }
 }
 
+/// Replace every `I`/`L` with `L`, since isoleucine and leucine are
+/// isobaric and indistinguishable by MS
+fn normalize_il(s: &str) -> String {
+    s.chars()
+        .map(|c| if c == 'I' { 'L' } else { c })
+        .collect()
+}
+
+/// Every (possibly overlapping) byte offset at which `needle` occurs in
+/// `haystack` - unlike `str::match_indices`, which skips past an entire
+/// match before resuming and so misses overlapping occurrences (e.g.
+/// `needle` inside a repetitive poly-Ala/Gly stretch).
+fn find_all(haystack: &str, needle: &str) -> Vec<usize> {
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let mut positions = Vec::new();
+    let mut start = 0;
+    while let Some(idx) = haystack[start..].find(needle) {
+        positions.push(start + idx);
+        start += idx + 1;
+    }
+    positions
+}
+
 impl Entry {
+    /// Locate `seq` within this entry's sequence, returning the first
+    /// matching site. Kept for callers that only care about one
+    /// candidate; prefer [`Entry::assign_residues`] when the peptide
+    /// might map ambiguously.
     pub fn assign_residue(&self, seq: &str) -> Option<usize> {
-        let peptide = if seq.contains(".") {
-            seq.split(".").skip(1).next()?
-        } else {
-            seq
+        self.assign_residues(seq).into_iter().next()
+    }
+
+    /// Locate every position at which `seq` matches this entry's
+    /// sequence, returning the (possibly several) candidate site
+    /// indices rather than arbitrarily taking the first hit.
+    ///
+    /// The exact substring search is tried first; if it finds nothing,
+    /// the search is retried against an I/L-normalized copy of both the
+    /// needle and `self.sequence`, since a tryptic peptide with an
+    /// Ile/Leu swap is indistinguishable by mass from the true sequence.
+    pub fn assign_residues(&self, seq: &str) -> Vec<usize> {
+        let peptide = match seq.contains(".") {
+            true => match seq.split(".").skip(1).next() {
+                Some(p) => p,
+                None => return Vec::new(),
+            },
+            false => seq,
         };
-        match peptide.find('*') {
-            Some(offset) => {
-                let needle = peptide.chars().filter(|&c| c != '*').collect::<String>();
-                self.sequence.find(&needle).map(|idx| idx + offset)
-            }
-            None => self.sequence.find(peptide),
+
+        let (needle, offset) = match peptide.find('*') {
+            Some(offset) => (
+                peptide.chars().filter(|&c| c != '*').collect::<String>(),
+                offset,
+            ),
+            None => (peptide.to_string(), 0),
+        };
+
+        let mut sites: Vec<usize> = find_all(&self.sequence, &needle)
+            .into_iter()
+            .map(|idx| idx + offset)
+            .collect();
+
+        if sites.is_empty() {
+            let normalized_needle = normalize_il(&needle);
+            let normalized_sequence = normalize_il(&self.sequence);
+            sites = find_all(&normalized_sequence, &normalized_needle)
+                .into_iter()
+                .map(|idx| idx + offset)
+                .collect();
         }
+
+        sites
     }
 }
@@ -0,0 +1,199 @@
+//! Parser for the Gene Ontology `go-basic.obo` flat file.
+//!
+//! Builds the GO term graph (`is_a`/`part_of` edges, namespace, and
+//! term name) as an adjacency structure keyed by GO ID, so that flat,
+//! per-protein GO annotations parsed by [`crate::kw`] can be propagated
+//! up to their ancestors for enrichment analysis.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Namespace {
+    BiologicalProcess,
+    MolecularFunction,
+    CellularComponent,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoTerm {
+    pub id: String,
+    pub name: String,
+    pub namespace: Namespace,
+    /// Direct `is_a`/`part_of` parents
+    pub parents: Vec<String>,
+}
+
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct GoDag {
+    pub terms: HashMap<String, GoTerm>,
+}
+
+impl GoDag {
+    /// Load and parse a `go-basic.obo` file into a [`GoDag`]
+    pub fn load<T: AsRef<Path>>(path: T) -> io::Result<GoDag> {
+        let f = File::open(path)?;
+        let reader = BufReader::new(f);
+
+        let mut dag = GoDag::default();
+        let mut in_term = false;
+        let mut obsolete = false;
+        let mut id = String::new();
+        let mut name = String::new();
+        let mut namespace = Namespace::BiologicalProcess;
+        let mut parents = Vec::new();
+
+        macro_rules! flush {
+            () => {
+                if in_term && !obsolete && !id.is_empty() {
+                    dag.terms.insert(
+                        id.clone(),
+                        GoTerm {
+                            id: id.clone(),
+                            name: name.clone(),
+                            namespace: namespace.clone(),
+                            parents: parents.clone(),
+                        },
+                    );
+                }
+            };
+        }
+
+        for line in reader.lines() {
+            let line = line?;
+            if line == "[Term]" {
+                flush!();
+                in_term = true;
+                obsolete = false;
+                id = String::new();
+                name = String::new();
+                namespace = Namespace::BiologicalProcess;
+                parents = Vec::new();
+            } else if line.starts_with('[') {
+                // Any other stanza (e.g. [Typedef]) ends the current term
+                flush!();
+                in_term = false;
+            } else if !in_term {
+                continue;
+            } else if let Some(v) = line.strip_prefix("id: ") {
+                id = v.to_string();
+            } else if let Some(v) = line.strip_prefix("name: ") {
+                name = v.to_string();
+            } else if let Some(v) = line.strip_prefix("namespace: ") {
+                namespace = match v {
+                    "molecular_function" => Namespace::MolecularFunction,
+                    "cellular_component" => Namespace::CellularComponent,
+                    _ => Namespace::BiologicalProcess,
+                };
+            } else if let Some(v) = line.strip_prefix("is_a: ") {
+                // "GO:0000001 ! some comment" - keep only the id
+                if let Some(term) = v.split_whitespace().next() {
+                    parents.push(term.to_string());
+                }
+            } else if let Some(v) = line.strip_prefix("relationship: part_of ") {
+                if let Some(term) = v.split_whitespace().next() {
+                    parents.push(term.to_string());
+                }
+            } else if line == "is_obsolete: true" {
+                obsolete = true;
+            }
+        }
+        flush!();
+
+        Ok(dag)
+    }
+
+    /// Walk `is_a`/`part_of` edges from `term` up to the root(s),
+    /// returning the full set of ancestor term IDs (not including
+    /// `term` itself).
+    pub fn ancestors(&self, term: &str) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut stack = match self.terms.get(term) {
+            Some(t) => t.parents.clone(),
+            None => return seen,
+        };
+
+        while let Some(parent) = stack.pop() {
+            if seen.insert(parent.clone()) {
+                if let Some(t) = self.terms.get(&parent) {
+                    stack.extend(t.parents.iter().cloned());
+                }
+            }
+        }
+        seen
+    }
+
+    /// Propagate a set of directly-annotated GO terms up the DAG,
+    /// returning the union of the terms themselves and all of their
+    /// ancestors.
+    pub fn propagate<'a, I: IntoIterator<Item = &'a str>>(&self, terms: I) -> HashSet<String> {
+        let mut expanded = HashSet::new();
+        for term in terms {
+            expanded.insert(term.to_string());
+            expanded.extend(self.ancestors(term));
+        }
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn write_obo(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("test-{}.obo", std::process::id()));
+        let mut f = File::create(&path).unwrap();
+        f.write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_terms_and_is_a() {
+        let path = write_obo(
+            "format-version: 1.2\n\n\
+             [Term]\n\
+             id: GO:0000001\n\
+             name: root process\n\
+             namespace: biological_process\n\n\
+             [Term]\n\
+             id: GO:0000002\n\
+             name: child process\n\
+             namespace: biological_process\n\
+             is_a: GO:0000001 ! root process\n",
+        );
+
+        let dag = GoDag::load(&path).unwrap();
+        assert_eq!(dag.terms.len(), 2);
+        assert_eq!(
+            dag.terms.get("GO:0000002").unwrap().parents,
+            vec![String::from("GO:0000001")]
+        );
+
+        let ancestors = dag.ancestors("GO:0000002");
+        assert!(ancestors.contains("GO:0000001"));
+        assert_eq!(ancestors.len(), 1);
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn skips_obsolete_terms() {
+        let path = write_obo(
+            "[Term]\n\
+             id: GO:0000003\n\
+             name: old process\n\
+             namespace: biological_process\n\
+             is_obsolete: true\n",
+        );
+
+        let dag = GoDag::load(&path).unwrap();
+        assert!(dag.terms.is_empty());
+
+        std::fs::remove_file(path).ok();
+    }
+}